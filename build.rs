@@ -0,0 +1,50 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[path = "src/preprocess.rs"]
+mod preprocess;
+
+/**
+ * vulkano-shader-derive can't resolve #include, so every shader is written
+ * with its includes pasted in to target/shaders, where shaders.rs loads them
+ * from. The modules in shaders/lib are only included, not compiled alone.
+ */
+fn shaders(directory: &Path, shaders: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if path.file_name().map_or(true, |n| n != "lib") {
+                self::shaders(&path, shaders)?;
+            }
+        } else if path.extension().map_or(false, |e| e == "glsl") {
+            shaders.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let source = root.join("shaders");
+    let target = root.join("target").join("shaders");
+
+    // Added and removed files change the directory
+    println!("cargo:rerun-if-changed={}", source.display());
+    println!("cargo:rerun-if-changed={}", source.join("lib").display());
+
+    let mut paths = vec![];
+    shaders(&source, &mut paths).expect("Failed to list shaders");
+
+    for path in paths {
+        let shader = preprocess::preprocess(&path).unwrap_or_else(|e| panic!("{}", e));
+        for file in &shader.files {
+            println!("cargo:rerun-if-changed={}", file.display());
+        }
+
+        let output = target.join(path.strip_prefix(&source).unwrap());
+        fs::create_dir_all(output.parent().unwrap()).unwrap();
+        fs::write(&output, shader.code).unwrap();
+    }
+}
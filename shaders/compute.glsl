@@ -0,0 +1,24 @@
+// Compute shader version of the raytracer, used by default. The fragment
+// shader in fragment.glsl traces the same way for comparison.
+
+#version 450
+
+#include "lib/common.glsl"
+
+// The workgroup size is given from Rust as specialization constants
+layout(local_size_x_id = 0, local_size_y_id = 1) in;
+
+// The HDR image that is post processed and tone mapped afterwards
+layout(set = 0, binding = 11, rgba16f) uniform writeonly image2D result;
+
+#include "lib/pixel.glsl"
+
+void main()
+{
+    // The last workgroups stick out of the tile if its size is not a multiple
+    if(gl_GlobalInvocationID.x >= push_data.tile_width || gl_GlobalInvocationID.y >= push_data.tile_height) return;
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy + uvec2(push_data.tile_x, push_data.tile_y));
+
+    // Same coordinates as gl_FragCoord, which points at the pixel center
+    imageStore(result, pixel, vec4(tracePixel(pixel, vec2(pixel) + 0.5), 1.0));
+}
@@ -0,0 +1,34 @@
+// Generating view rays
+
+#include "common.glsl"
+
+const float near_plane = 0.01;
+const float FOV = radians(90);
+
+void getEdges(in vec3 camera_pos, in vec3 camera_dir,
+        out vec3 upper_left, out vec3 upper_right, out vec3 lower_left) {
+
+    vec3 camera_up = vec3(0.0, 1.0, 0.0);
+    vec3 camera_right = cross(camera_dir, camera_up);
+    float plane_x_half = sin(FOV/2) * near_plane;
+    float plane_y_half = plane_x_half * push_data.height / push_data.width;
+
+    vec3 x = camera_right * plane_x_half;
+    vec3 y = camera_up * plane_y_half;
+    vec3 z = camera_dir * near_plane;
+
+    upper_left = camera_pos - x + y + z;
+    upper_right = camera_pos + x + y + z;
+    lower_left = camera_pos - x - y + z;
+}
+
+vec3 getRay(in vec3 camera_pos, in vec3 camera_dir, in vec2 pixel) {
+    vec3 u_l, u_r, l_l;
+    getEdges(camera_pos, camera_dir, u_l, u_r, l_l);
+
+    return normalize(
+        (u_l + (pixel.x / push_data.width) * (u_r - u_l)
+            + (pixel.y / push_data.height) * (l_l - u_l))
+        - camera_pos
+    );
+}
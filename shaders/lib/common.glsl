@@ -0,0 +1,181 @@
+// Constants, tuning parameters, push constants and the scene data bound
+// to the tracer shaders. The bindings must match the descriptor sets in Rust.
+
+const float PI = 3.1415926535897932384626433832795;
+const float EPSILON = 0.00001;
+const float INFINITY = 1.0 / 0.0;
+const uint UINT_MAX = 0xFFFFFFFF;
+
+// These definitions are tweakable.
+
+/* Minimum distance a ray must travel. Raising this value yields some performance
+ * benefits for secondary rays at the cost of weird artefacts around object
+ * edges.
+ */
+#define MIN_DIST 0.08
+/* Maximum distance a ray can travel. Changing it has little to no performance
+ * benefit for indoor scenes, but useful when there is nothing for the ray
+ * to intersect with (such as the sky in outdoors scenes).
+ */
+#define MAX_DIST 20.0
+/* Maximum number of steps the ray can march. High values make the image more
+ * correct around object edges at the cost of performance, lower values cause
+ * weird black hole-ish bending artefacts but is faster.
+ */
+#define MARCH_MAX_STEPS 128
+/* Typically, this doesn't have to be changed. Lower values cause worse
+ * performance, but make the tracing stabler around slightly incorrect distance
+ * functions.
+ * The current value merely helps with rounding errors.
+ */
+#define STEP_RATIO 0.999
+/* Determines what distance is considered close enough to count as an
+ * intersection. Lower values are more correct but require more steps to reach
+ * the surface
+ */
+#define HIT_RATIO 0.001
+/* Offset of shadow ray origins along the surface normal. Prevents surfaces from
+ * shadowing themselves due to rounding errors.
+ */
+#define SHADOW_BIAS 0.0005
+/* Number of shadow rays cast towards every area light. More samples give
+ * smoother penumbras at the cost of performance.
+ */
+#define AREA_LIGHT_SAMPLES 8
+/* Maximum number of bounces of a path in path tracing mode. Russian roulette
+ * usually terminates paths way earlier.
+ */
+#define MAX_PATH_BOUNCES 8
+/* Number of bounces after which russian roulette starts to terminate paths
+ * that carry little energy.
+ */
+#define ROULETTE_START 3
+/* Maximum number of reflected and refracted rays waiting to be traced. Limits
+ * the depth set from Rust.
+ */
+#define RAY_STACK_SIZE 8
+/* Number of importance sampled shadow rays towards the environment map per
+ * pixel when not path tracing.
+ */
+#define ENVIRONMENT_SAMPLES 4
+
+// Mouse coordinates
+//uniform vec2 u_mouse;
+
+layout(push_constant) uniform PushData {
+    vec4 camera_pos;
+    vec4 camera_dir;
+    float time;
+    uint width;
+    uint height;
+    // Number of frames accumulated since the image last changed
+    uint frame;
+    uint path_tracing;
+    // Maximum number of reflections and refractions along a path
+    uint max_depth;
+    // Rotation of the environment map around the Y axis in radians
+    float env_rotation;
+    // The tile being traced, only used by the compute shader
+    uint tile_x;
+    uint tile_y;
+    uint tile_width;
+    uint tile_height;
+} push_data;
+
+layout(set = 0, binding = 0) buffer VertexData {
+    vec3[] vertices;
+} vert;
+
+layout(set = 0, binding = 1) buffer IndexData {
+    uvec3[] indices;
+} idx;
+
+struct Node {
+    float[6] bounding_box;
+    uint left_child;
+    uint right_child;
+    uint parent;
+    uint is_leaf;
+};
+
+struct Ray {
+    vec3 orig;
+    vec3 dir;
+    vec3 dir_inv;
+};
+
+layout(set = 0, binding = 2) buffer BVH {
+    Node[] nodes;
+} bvh;
+
+#define LIGHT_POINT 0
+#define LIGHT_SPOT 1
+#define LIGHT_DIRECTIONAL 2
+#define LIGHT_AREA 3
+
+struct Light {
+    // Position of point and spot lights, corner of area lights
+    vec4 position;
+    // Direction the light shines to, normal for area lights
+    vec4 direction;
+    // Color in rgb, intensity in a
+    vec4 color;
+    // The two edges spanning an area light
+    vec4 edge_u;
+    vec4 edge_v;
+    uint kind;
+    // Cosines of the angles where a spot light starts to fade out and ends
+    float cos_inner;
+    float cos_outer;
+    float padding;
+};
+
+layout(set = 0, binding = 3) buffer LightData {
+    Light[] lights;
+} light_data;
+
+// Sum of all path traced samples of a pixel since the image last changed
+layout(set = 0, binding = 4, rgba32f) uniform image2D accumulation;
+
+// Equirectangular map of the light arriving from infinitely far away
+layout(set = 0, binding = 5) uniform sampler2D environment;
+
+/* Tables for importance sampling the environment map:
+ *  [0]     Average brightness of the map, 0 if it should not be sampled
+ *  then    Cumulative distribution of the rows (height + 1 entries)
+ *  then    Cumulative distribution in each row (height * (width + 1) entries)
+ *  then    Density of every pixel (width * height entries)
+ */
+layout(set = 0, binding = 6) buffer EnvironmentDistribution {
+    float[] data;
+} env_dist;
+
+// Surface properties of meshes, see material::Material
+struct MeshMaterial {
+    vec4 color;
+    float roughness;
+    float specular;
+    float reflectivity;
+    float transparency;
+    float ior;
+    // Layers of the texture array, -1 if not textured
+    int albedo_texture;
+    int normal_texture;
+    int roughness_texture;
+};
+
+layout(set = 0, binding = 7) buffer MaterialData {
+    MeshMaterial[] materials;
+} material_data;
+
+// Texture coordinates of the vertices
+layout(set = 0, binding = 8) buffer UVData {
+    vec2[] uvs;
+} uv_data;
+
+layout(set = 0, binding = 9) uniform sampler2DArray textures;
+
+// Normals of the vertices, zero where triangles are shaded flat
+layout(set = 0, binding = 10) buffer NormalData {
+    vec3[] normals;
+} normal_data;
@@ -0,0 +1,116 @@
+// The equirectangular environment map and importance sampling it
+
+#include "common.glsl"
+#include "rotation.glsl"
+
+// Maps a direction to coordinates in the equirectangular environment map
+vec2 directionToEquirect(vec3 d)
+{
+    d = rot_y(d, -push_data.env_rotation);
+    return vec2(atan(d.x, -d.z) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+}
+
+vec3 equirectToDirection(vec2 uv)
+{
+    float phi = 2.0 * PI * (uv.x - 0.5);
+    float theta = PI * uv.y;
+    vec3 d = vec3(sin(theta) * sin(phi), cos(theta), -sin(theta) * cos(phi));
+    return rot_y(d, push_data.env_rotation);
+}
+
+// The light arriving along rays that do not hit anything
+vec3 background(vec3 v)
+{
+    return textureLod(environment, directionToEquirect(v), 0.0).rgb;
+}
+
+bool hasEnvironmentLight()
+{
+    return env_dist.data[0] > 0.0;
+}
+
+/* Finds the interval of a cumulative distribution containing xi.
+ *
+ * Parameters:
+ *  offset  Start of the distribution in the environment tables
+ *  count   Number of intervals
+ *  xi      Uniform random number
+ *
+ * Returns:
+ *  The index of the interval.
+ */
+uint searchCDF(uint offset, uint count, float xi)
+{
+    uint lo = 0;
+    uint hi = count;
+    while(lo + 1 < hi) {
+        uint mid = (lo + hi) / 2;
+        if(env_dist.data[offset + mid] <= xi) lo = mid;
+        else hi = mid;
+    }
+    return lo;
+}
+
+// Position of xi inside its interval, as a uniform random number again
+float remapCDF(uint offset, uint index, float xi)
+{
+    float lo = env_dist.data[offset + index];
+    float hi = env_dist.data[offset + index + 1];
+    return clamp((xi - lo) / max(hi - lo, 1e-12), 0.0, 1.0);
+}
+
+// Probability density of a pixel of the environment map, per solid angle
+float environmentDensity(uvec2 pixel, float theta)
+{
+    ivec2 size = textureSize(environment, 0);
+    uint w = uint(size.x);
+    uint h = uint(size.y);
+    uint densities = 1 + (h + 1) + h * (w + 1);
+
+    float sin_theta = sin(theta);
+    if(sin_theta <= 0.0) return 0.0;
+    return env_dist.data[densities + pixel.y * w + pixel.x] / (2.0 * PI * PI * sin_theta);
+}
+
+/* Draws a direction towards the environment, proportional to its brightness.
+ *
+ * Parameters:
+ *  xi      Uniform random numbers
+ *  dir     The drawn direction
+ *  pdf     Probability density of the direction
+ *
+ * Returns:
+ *  The light arriving from the direction.
+ */
+vec3 sampleEnvironment(in vec2 xi, out vec3 dir, out float pdf)
+{
+    ivec2 size = textureSize(environment, 0);
+    uint w = uint(size.x);
+    uint h = uint(size.y);
+    uint marginal = 1;
+    uint conditional = marginal + h + 1;
+
+    uint row = searchCDF(marginal, h, xi.y);
+    uint row_offset = conditional + row * (w + 1);
+    uint column = searchCDF(row_offset, w, xi.x);
+
+    vec2 uv = vec2(
+        (float(column) + remapCDF(row_offset, column, xi.x)) / float(w),
+        (float(row) + remapCDF(marginal, row, xi.y)) / float(h)
+    );
+
+    dir = equirectToDirection(uv);
+    pdf = environmentDensity(uvec2(column, row), PI * uv.y);
+    return textureLod(environment, uv, 0.0).rgb;
+}
+
+// Probability density of sampleEnvironment() drawing the direction
+float environmentPdf(vec3 dir)
+{
+    if(!hasEnvironmentLight()) return 0.0;
+
+    vec2 size = vec2(textureSize(environment, 0));
+    vec2 uv = directionToEquirect(dir);
+    uvec2 pixel = uvec2(min(uv * size, size - 1.0));
+    return environmentDensity(pixel, PI * uv.y);
+}
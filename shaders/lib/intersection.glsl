@@ -0,0 +1,41 @@
+// Ray-triangle intersection
+
+#include "common.glsl"
+
+Ray makeRay(in vec3 o, in vec3 v) {
+    Ray ray;
+    ray.orig = o;
+    ray.dir = v;
+    ray.dir_inv = 1/v;
+    return ray;
+}
+
+// Möller-Trumbore algorithm, from Wikipedia
+bool testIntersection(in Ray r, in vec3 camera_pos, in vec3 v0, in vec3 v1, in vec3 v2, out vec3 intersection, out float dist, out vec2 barycentric) {
+    vec3 ray = r.dir;
+    vec3 edge1 = v1 - v0;
+    vec3 edge2 = v2 - v0;
+    vec3 h = cross(ray, edge2);
+    float a = dot(edge1, h);
+    // Parallel ray, relative to the size of the triangle so that small
+    // triangles of subdivided models are still hit
+    if(a * a < EPSILON * EPSILON * dot(edge1, edge1) * dot(edge2, edge2)) return false;
+
+    float f = 1.0 / a;
+    vec3 s = camera_pos - v0;
+    float u = f * dot(s, h);
+    if(u < 0.0 || u > 1.0) return false;
+
+    vec3 q = cross(s, edge1);
+    float v = f * dot(ray, q);
+    if(v < 0.0 || u + v > 1.0) return false;
+
+    float t = f * dot(edge2, q);
+    if(t > EPSILON) {
+        intersection = camera_pos + ray * t;
+        dist = t;
+        barycentric = vec2(u, v);
+        return true;
+    }
+    return false;
+}
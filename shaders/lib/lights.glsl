@@ -0,0 +1,51 @@
+// Light sources of the scene
+
+#include "common.glsl"
+
+/* Calculates the light arriving at a point from a light source.
+ *
+ * Parameters:
+ *  light       The light source
+ *  p           The point that is lit
+ *  xi          Random numbers choosing the point on area lights
+ *  light_dir   Direction from p towards the light
+ *  light_dist  Distance from p to the light, INFINITY for directional lights
+ *
+ * Returns:
+ *  The radiance arriving at p, without any occlusion.
+ */
+vec3 sampleLight(
+    in Light light,
+    in vec3 p,
+    in vec2 xi,
+    out vec3 light_dir,
+    out float light_dist
+) {
+    vec3 radiance = light.color.rgb * light.color.a;
+
+    if(light.kind == LIGHT_DIRECTIONAL) {
+        light_dir = -light.direction.xyz;
+        light_dist = INFINITY;
+        return radiance;
+    }
+
+    vec3 light_pos = light.position.xyz;
+    if(light.kind == LIGHT_AREA) {
+        light_pos += xi.x * light.edge_u.xyz + xi.y * light.edge_v.xyz;
+    }
+
+    vec3 to_light = light_pos - p;
+    light_dist = length(to_light);
+    light_dir = to_light / light_dist;
+    float attenuation = 1.0 / (light_dist * light_dist);
+
+    if(light.kind == LIGHT_SPOT) {
+        float cos_angle = dot(-light_dir, light.direction.xyz);
+        attenuation *= smoothstep(light.cos_outer, light.cos_inner, cos_angle);
+    } else if(light.kind == LIGHT_AREA) {
+        float area = length(cross(light.edge_u.xyz, light.edge_v.xyz));
+        attenuation *= area * max(dot(-light_dir, light.direction.xyz), 0.0);
+    }
+
+    return radiance * attenuation;
+}
@@ -0,0 +1,165 @@
+// Surface materials, textures and the Fresnel and GGX terms of the BRDF
+
+#include "common.glsl"
+
+struct material
+{
+    // The color of the surface
+    vec4 color;
+    // Roughness of the specular reflection, from mirror-like 0 to rough 1
+    float roughness;
+    // Reflectance of the specular reflection at normal incidence
+    float specular;
+    // Fraction of light reflected like a perfect mirror
+    float reflectivity;
+    // Fraction of light refracted into the surface, reflected by Fresnel
+    float transparency;
+    // Index of refraction of transparent materials
+    float ior;
+    // You can add your own material features here!
+};
+
+material makeMaterial(vec4 color)
+{
+    material mat;
+    mat.color = color;
+    mat.roughness = 0.5;
+    mat.specular = 0.04;
+    mat.reflectivity = 0.0;
+    mat.transparency = 0.0;
+    mat.ior = 1.5;
+    return mat;
+}
+
+vec3 srgbToLinear(vec3 c)
+{
+    return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), greaterThan(c, vec3(0.04045)));
+}
+
+vec4 sampleTexture(int layer, vec2 uv)
+{
+    return textureLod(textures, vec3(uv, float(layer)), 0.0);
+}
+
+float fresnelSchlick(float f0, float cos_theta)
+{
+    return f0 + (1.0 - f0) * pow(1.0 - clamp(cos_theta, 0.0, 1.0), 5.0);
+}
+
+/* Splits the light hitting a surface into a reflected and a refracted part,
+ * using Schlick's approximation of the Fresnel equations.
+ *
+ * Parameters:
+ *  mat         Material of the surface
+ *  n           Normal of the surface, facing the ray
+ *  v           Direction of the ray
+ *  front_face  Whether the ray enters the surface or leaves it
+ *  kr          Fraction of mirror reflected light
+ *  kt          Fraction of refracted light
+ *  refracted   Direction of the refracted ray
+ */
+void fresnelSplit(
+    in material mat,
+    in vec3 n,
+    in vec3 v,
+    in bool front_face,
+    out float kr,
+    out float kt,
+    out vec3 refracted
+) {
+    kr = mat.reflectivity;
+    kt = 0.0;
+    refracted = vec3(0.0);
+    if(mat.transparency <= 0.0) return;
+
+    float eta = front_face ? 1.0 / mat.ior : mat.ior;
+    refracted = refract(v, n, eta);
+
+    float f = 1.0; // total internal reflection
+    if(refracted != vec3(0.0)) {
+        // Schlick has to use the angle on the side of the thinner medium
+        float cos_theta = front_face ? dot(-v, n) : dot(refracted, -n);
+        float r0 = (1.0 - mat.ior) / (1.0 + mat.ior);
+        f = fresnelSchlick(r0 * r0, cos_theta);
+    }
+
+    kr += mat.transparency * f;
+    kt = mat.transparency * (1.0 - f);
+}
+
+// GGX normal distribution function
+float ggxDistribution(float n_dot_h, float alpha)
+{
+    float a2 = alpha * alpha;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Smith masking function for GGX, for either the light or the view direction
+float smithG1(float n_dot_x, float alpha)
+{
+    float a2 = alpha * alpha;
+    return 2.0 * n_dot_x / (n_dot_x + sqrt(a2 + (1.0 - a2) * n_dot_x * n_dot_x));
+}
+
+float ggxAlpha(in material mat)
+{
+    return max(mat.roughness * mat.roughness, 0.001);
+}
+
+// Probability of sampling the specular lobe, higher at grazing angles
+float specularChance(in material mat, float n_dot_v)
+{
+    return clamp(fresnelSchlick(mat.specular, n_dot_v), 0.1, 0.9);
+}
+
+/* Evaluates the Lambert diffuse and GGX specular BRDF.
+ *
+ * Parameters:
+ *  n   Normal of the surface
+ *  wo  Direction towards the viewer
+ *  wi  Direction towards the light
+ *  mat Material of the surface
+ *
+ * Returns:
+ *  The BRDF multiplied with the cosine of the incoming light.
+ */
+vec3 evalBRDF(in vec3 n, in vec3 wo, in vec3 wi, in material mat)
+{
+    float n_dot_l = dot(n, wi);
+    float n_dot_v = dot(n, wo);
+    if(n_dot_l <= 0.0 || n_dot_v <= 0.0) return vec3(0.0);
+
+    vec3 h = normalize(wo + wi);
+    float alpha = ggxAlpha(mat);
+    float f = fresnelSchlick(mat.specular, dot(wo, h));
+    float g = smithG1(n_dot_v, alpha) * smithG1(n_dot_l, alpha);
+    float spec = ggxDistribution(dot(n, h), alpha) * g * f / (4.0 * n_dot_v * n_dot_l);
+
+    return (mat.color.rgb / PI + vec3(spec)) * n_dot_l;
+}
+
+/* Probability density of pathTrace() drawing a direction from the BRDF.
+ *
+ * Parameters:
+ *  n   Normal of the surface
+ *  wo  Direction towards the viewer
+ *  wi  The drawn direction
+ *  mat Material of the surface
+ *
+ * Returns:
+ *  The density per solid angle.
+ */
+float brdfPdf(in vec3 n, in vec3 wo, in vec3 wi, in material mat)
+{
+    float n_dot_l = dot(n, wi);
+    if(n_dot_l <= 0.0) return 0.0;
+
+    vec3 h = normalize(wo + wi);
+    float n_dot_h = dot(n, h);
+    float specular = ggxDistribution(n_dot_h, ggxAlpha(mat)) * n_dot_h
+        / (4.0 * max(dot(wo, h), EPSILON));
+    float chance = specularChance(mat, dot(n, wo));
+
+    return chance * specular + (1.0 - chance) * n_dot_l / PI;
+}
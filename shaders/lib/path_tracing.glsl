@@ -0,0 +1,129 @@
+// Progressive path tracing with multiple importance sampling
+
+#include "common.glsl"
+#include "environment.glsl"
+#include "lights.glsl"
+#include "materials.glsl"
+#include "sampling.glsl"
+#include "scene.glsl"
+
+/* Samples every light once and accumulates the unoccluded contributions.
+ *
+ * Parameters:
+ *  p   The point that is lit
+ *  n   Normal of the surface
+ *  wo  Direction towards the viewer
+ *  mat Material of the surface
+ *
+ * Returns:
+ *  The light reflected towards the viewer.
+ */
+vec3 directLight(in vec3 p, in vec3 n, in vec3 wo, in material mat)
+{
+    vec3 color = vec3(0.0);
+    for(uint i = 0; i < light_data.lights.length(); ++i) {
+        vec3 light_dir;
+        float light_dist;
+        vec2 xi = vec2(random(), random());
+        vec3 radiance = sampleLight(light_data.lights[i], p, xi, light_dir, light_dist);
+
+        if(dot(light_dir, n) <= 0.0) continue;
+        if(occluded(p + n * SHADOW_BIAS, light_dir, min(light_dist, MAX_DIST))) continue;
+
+        color += evalBRDF(n, wo, light_dir, mat) * radiance;
+    }
+
+    if(hasEnvironmentLight()) {
+        vec3 env_dir;
+        float env_pdf;
+        vec3 radiance = sampleEnvironment(vec2(random(), random()), env_dir, env_pdf);
+
+        if(env_pdf > 0.0 && dot(env_dir, n) > 0.0
+                && !occluded(p + n * SHADOW_BIAS, env_dir, MAX_DIST)) {
+            float weight = powerHeuristic(env_pdf, brdfPdf(n, wo, env_dir, mat));
+            color += evalBRDF(n, wo, env_dir, mat) * radiance * weight / env_pdf;
+        }
+    }
+
+    return color;
+}
+
+/* Follows a single random path through the scene. Light sources are sampled
+ * at every vertex, the next direction is drawn from either the diffuse or the
+ * specular lobe of the BRDF.
+ *
+ * Parameters:
+ *  o   Origin of the view ray
+ *  v   Direction of the view ray
+ *
+ * Returns:
+ *  Estimate of the light arriving along the view ray.
+ */
+vec3 pathTrace(vec3 o, vec3 v)
+{
+    vec3 color = vec3(0.0);
+    vec3 throughput = vec3(1.0);
+    // Density of the last BRDF sample, 0 for camera rays and perfect mirrors
+    float brdf_pdf = 0.0;
+
+    for(int bounce = 0; bounce < MAX_PATH_BOUNCES; ++bounce) {
+        vec3 p, n;
+        material mat;
+        bool front_face;
+        if(!intersect(o, v, MAX_DIST, p, n, mat, front_face, insideSDF(o, v))) {
+            // The environment was sampled directly as well, so both are weighted
+            float weight = brdf_pdf > 0.0 ? powerHeuristic(brdf_pdf, environmentPdf(v)) : 1.0;
+            color += throughput * background(v) * weight;
+            break;
+        }
+
+        // Mirror reflection and refraction are picked with their probability,
+        // which cancels out with their weight
+        float kr, kt;
+        vec3 refracted;
+        fresnelSplit(mat, n, v, front_face, kr, kt, refracted);
+
+        float choice = random();
+        if(choice < kr) {
+            v = reflect(v, n);
+            o = p + n * SHADOW_BIAS;
+            brdf_pdf = 0.0;
+            continue;
+        }
+        if(choice < kr + kt) {
+            v = refracted;
+            o = p - n * SHADOW_BIAS;
+            throughput *= mat.color.rgb;
+            brdf_pdf = 0.0;
+            continue;
+        }
+
+        vec3 wo = -v;
+        float n_dot_v = dot(n, wo);
+        if(n_dot_v <= 0.0) break;
+
+        color += throughput * directLight(p, n, wo, mat);
+
+        // Pick a lobe, preferring the specular one at grazing angles
+        if(random() < specularChance(mat, n_dot_v)) {
+            vec3 h = sampleGGX(n, ggxAlpha(mat), vec2(random(), random()));
+            v = reflect(-wo, h);
+        } else {
+            v = sampleCosineHemisphere(n, vec2(random(), random()));
+        }
+
+        brdf_pdf = brdfPdf(n, wo, v, mat);
+        if(brdf_pdf <= 0.0) break;
+        throughput *= evalBRDF(n, wo, v, mat) / brdf_pdf;
+
+        if(bounce >= ROULETTE_START) {
+            float survival = clamp(max(throughput.r, max(throughput.g, throughput.b)), 0.05, 0.95);
+            if(random() > survival) break;
+            throughput /= survival;
+        }
+
+        o = p + n * SHADOW_BIAS;
+    }
+
+    return color;
+}
@@ -0,0 +1,42 @@
+// Tracing a single pixel, shared by the fragment and the compute shader
+
+#include "common.glsl"
+#include "camera.glsl"
+#include "path_tracing.glsl"
+#include "render.glsl"
+#include "sampling.glsl"
+
+/* Calculates the color of a pixel in the current mode. Path traced samples
+ * are averaged over the frames in the accumulation image.
+ *
+ * Parameters:
+ *  pixel   Integer coordinates of the pixel
+ *  coord   Position of the pixel center, like gl_FragCoord
+ *
+ * Returns:
+ *  The HDR color of the pixel.
+ */
+vec3 tracePixel(ivec2 pixel, vec2 coord)
+{
+    seedRandom(uvec2(pixel), push_data.frame * 7919u + floatBitsToUint(push_data.time));
+
+    // Origin of the view ray
+    vec3 o = push_data.camera_pos.xyz;
+
+    vec3 dir = push_data.camera_dir.xyz;
+
+    if(push_data.path_tracing == 0) {
+        return render(o, getRay(o, dir, coord));
+    }
+
+    // Jittering the ray inside the pixel antialiases the accumulated image
+    vec2 jitter = vec2(random(), random()) - 0.5;
+    vec3 color = pathTrace(o, getRay(o, dir, coord + jitter));
+    if(any(isnan(color)) || any(isinf(color))) color = vec3(0.0);
+
+    vec3 sum = color;
+    if(push_data.frame > 0) sum += imageLoad(accumulation, pixel).rgb;
+    imageStore(accumulation, pixel, vec4(sum, 1.0));
+
+    return sum / float(push_data.frame + 1);
+}
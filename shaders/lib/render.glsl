@@ -0,0 +1,147 @@
+// Direct lighting with mirror reflections and refractions, the default mode
+
+#include "common.glsl"
+#include "environment.glsl"
+#include "lights.glsl"
+#include "materials.glsl"
+#include "sampling.glsl"
+#include "scene.glsl"
+
+/* Phong shading of a surface point lit from a single direction.
+ *
+ * Parameters:
+ *  n           Normal of the surface
+ *  v           Direction of the view ray
+ *  mat         Material of the surface
+ *  light_dir   Direction towards the light
+ *
+ * Returns:
+ *  The fraction of the incoming light reflected towards the viewer.
+ */
+vec3 phong(in vec3 n, in vec3 v, in material mat, in vec3 light_dir)
+{
+    float l = max(dot(light_dir, n), 0.0);
+    float s = 0.0;
+
+    if(l > 0.0) {
+        vec3 refl_dir = reflect(-light_dir, n);
+        float angle = max(dot(refl_dir, v), 0.0);
+        s = pow(angle, 4.0);
+    }
+
+    return l * mat.color.rgb + s * vec3(1.0);
+}
+
+/* Lights a surface point with all light sources.
+ *
+ * Parameters:
+ *  p   The point to shade
+ *  n   Normal of the surface
+ *  v   Direction of the view ray
+ *  mat Material of the surface
+ *
+ * Returns:
+ *  The light reflected towards the viewer.
+ */
+vec3 shade(vec3 p, vec3 n, vec3 v, material mat)
+{
+    vec3 color = vec3(0.0);
+    for(uint i = 0; i < light_data.lights.length(); ++i) {
+        Light light = light_data.lights[i];
+        // Area lights need many shadow rays to get smooth penumbras
+        int samples = light.kind == LIGHT_AREA ? AREA_LIGHT_SAMPLES : 1;
+        vec3 light_color = vec3(0.0);
+
+        for(int j = 0; j < samples; ++j) {
+            vec2 xi = light.kind == LIGHT_AREA ? vec2(random(), random()) : vec2(0.5);
+            vec3 light_dir;
+            float light_dist;
+            vec3 radiance = sampleLight(light, p, xi, light_dir, light_dist);
+
+            if(dot(light_dir, n) <= 0.0) continue;
+            if(occluded(p + n * SHADOW_BIAS, light_dir, min(light_dist, MAX_DIST))) continue;
+
+            light_color += phong(n, v, mat, light_dir) * radiance;
+        }
+
+        color += light_color / float(samples);
+    }
+
+    if(hasEnvironmentLight()) {
+        vec3 env_color = vec3(0.0);
+        for(int j = 0; j < ENVIRONMENT_SAMPLES; ++j) {
+            vec3 env_dir;
+            float env_pdf;
+            vec3 radiance = sampleEnvironment(vec2(random(), random()), env_dir, env_pdf);
+
+            if(env_pdf <= 0.0 || dot(env_dir, n) <= 0.0) continue;
+            if(occluded(p + n * SHADOW_BIAS, env_dir, MAX_DIST)) continue;
+
+            env_color += phong(n, v, mat, env_dir) * radiance / env_pdf;
+        }
+        color += env_color / float(ENVIRONMENT_SAMPLES);
+    }
+
+    return color;
+}
+
+// A reflected or refracted ray waiting to be traced by render()
+struct PendingRay {
+    vec3 o;
+    vec3 v;
+    // How much the ray contributes to the pixel
+    vec3 weight;
+    uint depth;
+};
+
+/* Calculates the color of the pixel, based on view ray origin and direction.
+ * GLSL has no recursion, so reflected and refracted rays are put on a stack
+ * and traced one after another.
+ *
+ * Parameters:
+ *  o   Origin of the view ray
+ *  v   Direction of the view ray
+ *
+ * Returns:
+ *  Color of the pixel.
+ */
+vec3 render(vec3 o, vec3 v)
+{
+    PendingRay stack[RAY_STACK_SIZE];
+    stack[0] = PendingRay(o, v, vec3(1.0), 0u);
+    int count = 1;
+
+    vec3 color = vec3(0.0);
+    while(count > 0) {
+        PendingRay ray = stack[--count];
+
+        vec3 p, n;
+        material mat;
+        bool front_face;
+
+        // Compute intersection point along the view ray.
+        if(!intersect(ray.o, ray.v, MAX_DIST, p, n, mat, front_face, insideSDF(ray.o, ray.v))) {
+            color += ray.weight * background(ray.v);
+            continue;
+        }
+
+        float kr, kt;
+        vec3 refracted;
+        fresnelSplit(mat, n, ray.v, front_face, kr, kt, refracted);
+
+        color += ray.weight * (1.0 - kr - kt) * shade(p, n, ray.v, mat);
+
+        if(ray.depth >= push_data.max_depth) continue;
+
+        if(kt > 0.0 && count < RAY_STACK_SIZE) {
+            vec3 weight = ray.weight * kt * mat.color.rgb;
+            stack[count++] = PendingRay(p - n * SHADOW_BIAS, refracted, weight, ray.depth + 1u);
+        }
+        if(kr > 0.0 && count < RAY_STACK_SIZE) {
+            vec3 weight = ray.weight * kr;
+            stack[count++] = PendingRay(p + n * SHADOW_BIAS, reflect(ray.v, n), weight, ray.depth + 1u);
+        }
+    }
+
+    return color;
+}
@@ -0,0 +1,61 @@
+// Rotations around the coordinate axes
+
+/* Rotates point around origin along the X axis.
+ *
+ * Parameters:
+ *  p   The point to rotate
+ *  a   The angle in radians
+ *
+ * Returns:
+ *  The rotated point.
+ */
+vec3 rot_x(vec3 p, float a)
+{
+    float s = sin(a);
+    float c = cos(a);
+    return vec3(
+        p.x,
+        c*p.y-s*p.z,
+        s*p.y+c*p.z
+    );
+}
+
+/* Rotates point around origin along the Y axis.
+ *
+ * Parameters:
+ *  p   The point to rotate
+ *  a   The angle in radians
+ *
+ * Returns:
+ *  The rotated point.
+ */
+vec3 rot_y(vec3 p, float a)
+{
+    float s = sin(a);
+    float c = cos(a);
+    return vec3(
+        c*p.x+s*p.z,
+        p.y,
+        -s*p.x+c*p.z
+    );
+}
+
+/* Rotates point around origin along the Z axis.
+ *
+ * Parameters:
+ *  p   The point to rotate
+ *  a   The angle in radians
+ *
+ * Returns:
+ *  The rotated point.
+ */
+vec3 rot_z(vec3 p, float a)
+{
+    float s = sin(a);
+    float c = cos(a);
+    return vec3(
+        c*p.x-s*p.y,
+        s*p.x+c*p.y,
+        p.z
+    );
+}
@@ -0,0 +1,66 @@
+// Random numbers and drawing random directions
+
+#include "common.glsl"
+
+// State of the random number generator, seeded once per pixel
+uint rng_state;
+
+// PCG hash, a cheap but good quality integer hash
+uint hash(uint v)
+{
+    uint state = v * 747796405u + 2891336453u;
+    uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+void seedRandom(uvec2 pixel, uint seed)
+{
+    rng_state = hash(pixel.x + hash(pixel.y + hash(seed)));
+}
+
+// Returns a uniformly distributed random number in [0, 1)
+float random()
+{
+    rng_state = hash(rng_state);
+    return float(rng_state) / 4294967296.0;
+}
+
+// Weight for combining two sampling strategies, see Veach's thesis
+float powerHeuristic(float pdf, float other_pdf)
+{
+    float a = pdf * pdf;
+    float b = other_pdf * other_pdf;
+    return a + b > 0.0 ? a / (a + b) : 0.0;
+}
+
+/* Builds an orthonormal basis with the normal as z axis.
+ *
+ * Parameters:
+ *  n   The normal
+ *
+ * Returns:
+ *  Matrix transforming from tangent space to world space.
+ */
+mat3 tangentSpace(vec3 n)
+{
+    vec3 up = abs(n.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 t = normalize(cross(up, n));
+    return mat3(t, cross(n, t), n);
+}
+
+// Draws a direction around n with a probability proportional to the cosine
+vec3 sampleCosineHemisphere(vec3 n, vec2 xi)
+{
+    float r = sqrt(xi.x);
+    float phi = 2.0 * PI * xi.y;
+    return tangentSpace(n) * vec3(r * cos(phi), r * sin(phi), sqrt(max(1.0 - xi.x, 0.0)));
+}
+
+// Draws a microfacet normal around n proportional to D(h) * cos(theta_h)
+vec3 sampleGGX(vec3 n, float alpha, vec2 xi)
+{
+    float cos_theta = sqrt((1.0 - xi.x) / (1.0 + (alpha * alpha - 1.0) * xi.x));
+    float sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    float phi = 2.0 * PI * xi.y;
+    return tangentSpace(n) * vec3(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+}
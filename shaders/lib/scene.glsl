@@ -0,0 +1,180 @@
+// Intersecting rays with the whole scene, the mesh and the distance field
+
+#include "common.glsl"
+#include "materials.glsl"
+#include "sdf.glsl"
+#include "traversal.glsl"
+
+/* Looks up the material and shading normal where a ray hit a triangle.
+ *
+ * Parameters:
+ *  ray         The ray that hit the triangle
+ *  node        Leaf node of the triangle
+ *  index       Index into the material buffer
+ *  n           Normal of the surface, perturbed by the normal map
+ *  mat         Material of the surface, with textures applied
+ */
+void meshSurface(in Ray ray, in uint node, in uint index, out vec3 n, out material mat)
+{
+    uvec3 idx = getIndices(node);
+    vec3 v0 = vert.vertices[idx.x];
+    vec3 v1 = vert.vertices[idx.y];
+    vec3 v2 = vert.vertices[idx.z];
+    vec2 uv0 = uv_data.uvs[idx.x];
+    vec2 uv1 = uv_data.uvs[idx.y];
+    vec2 uv2 = uv_data.uvs[idx.z];
+
+    vec3 p;
+    float t;
+    vec2 b;
+    testIntersection(ray, ray.orig, v0, v1, v2, p, t, b);
+    vec2 uv = (1.0 - b.x - b.y) * uv0 + b.x * uv1 + b.y * uv2;
+
+    MeshMaterial m = material_data.materials[index];
+    mat = makeMaterial(m.color);
+    mat.roughness = m.roughness;
+    mat.specular = m.specular;
+    mat.reflectivity = m.reflectivity;
+    mat.transparency = m.transparency;
+    mat.ior = m.ior;
+
+    if(m.albedo_texture >= 0) {
+        vec4 albedo = sampleTexture(m.albedo_texture, uv);
+        mat.color = vec4(srgbToLinear(albedo.rgb), albedo.a);
+    }
+    if(m.roughness_texture >= 0) {
+        mat.roughness *= sampleTexture(m.roughness_texture, uv).g;
+    }
+
+    vec3 e1 = v1 - v0;
+    vec3 e2 = v2 - v0;
+    n = normalize(cross(e1, e2));
+
+    // Smooth normals turned towards the geometric one, so they are on the
+    // same side as front_face expects
+    vec3 smooth_n = (1.0 - b.x - b.y) * normal_data.normals[idx.x]
+        + b.x * normal_data.normals[idx.y]
+        + b.y * normal_data.normals[idx.z];
+    if(length(smooth_n) > EPSILON) {
+        n = normalize(smooth_n) * sign(dot(smooth_n, n) + EPSILON);
+    }
+
+    vec2 duv1 = uv1 - uv0;
+    vec2 duv2 = uv2 - uv0;
+    float det = duv1.x * duv2.y - duv1.y * duv2.x;
+    if(m.normal_texture >= 0 && abs(det) > EPSILON) {
+        // Tangent and bitangent follow the texture coordinates over the triangle
+        vec3 tangent = (e1 * duv2.y - e2 * duv1.y) / det;
+        vec3 bitangent = (e2 * duv1.x - e1 * duv2.x) / det;
+        tangent = normalize(tangent - n * dot(n, tangent));
+        bitangent = normalize(bitangent - n * dot(n, bitangent) - tangent * dot(tangent, bitangent));
+
+        vec3 mapped = sampleTexture(m.normal_texture, uv).xyz * 2.0 - 1.0;
+        n = normalize(tangent * mapped.x + bitangent * mapped.y + n * mapped.z);
+    }
+}
+
+/* Finds the closest intersection of the ray with the scene.
+ *
+ * Parameters:
+ *  o           Origin of the ray
+ *  v           Direction of the ray
+ *  max_dist    Maximum distance the ray can travel. Usually MAX_DIST.
+ *  p           Location of the intersection
+ *  n           Normal of the surface at the intersection point
+ *  mat         Material of the intersected surface
+ *  front_face  Whether the ray hit the outside of the surface. The normal
+ *              always faces the ray.
+ *  inside      Whether we are marching inside an object or not. Useful for
+ *              refractions.
+ *
+ * Returns:
+ *  true if a surface was hit, false otherwise.
+ */
+bool intersect(
+    in vec3 o,
+    in vec3 v,
+    in float max_dist,
+    out vec3 p,
+    out vec3 n,
+    out material mat,
+    out bool front_face,
+    bool inside
+) {
+    float t = MIN_DIST;
+    float dir = inside ? -1.0 : 1.0;
+    bool hit = false;
+
+    // The distance field only has to be marched up to the closest triangle
+    float mesh_dist;
+    uint node;
+    bool mesh_hit = traverseBVH(makeRay(o, v), max_dist, false, mesh_dist, node);
+    float march_dist = mesh_hit ? mesh_dist : max_dist;
+
+    for(int i = 0; i < MARCH_MAX_STEPS; ++i)
+    {
+        p = o + t * v;
+        float dist = dir * map(p, mat);
+
+        hit = abs(dist) < HIT_RATIO * t;
+
+        if(hit || t > march_dist) break;
+
+        t += dist * STEP_RATIO;
+    }
+
+    if(hit) {
+        n = normal(p, mat);
+        front_face = dot(n, v) < 0.0;
+    } else if(mesh_hit) {
+        uvec3 idx = getIndices(node);
+        vec3 v0 = vert.vertices[idx.x];
+        vec3 v1 = vert.vertices[idx.y];
+        vec3 v2 = vert.vertices[idx.z];
+
+        p = o + mesh_dist * v;
+        // The geometric normal decides the side, normal maps only shade
+        front_face = dot(cross(v1 - v0, v2 - v0), v) < 0.0;
+        meshSurface(makeRay(o, v), node, getMaterial(node), n, mat);
+    }
+
+    if(!front_face) n = -n;
+
+    return hit || mesh_hit;
+}
+
+/* Checks if anything blocks the path between a point and a light.
+ *
+ * Parameters:
+ *  o           Origin of the shadow ray
+ *  v           Direction towards the light
+ *  max_dist    Distance to the light
+ *
+ * Returns:
+ *  true if the light is blocked, false otherwise.
+ */
+bool occluded(in vec3 o, in vec3 v, in float max_dist)
+{
+    float dist;
+    uint node;
+    if(traverseBVH(makeRay(o, v), max_dist, true, dist, node)) return true;
+
+    material mat;
+    float t = MIN_DIST;
+    for(int i = 0; i < MARCH_MAX_STEPS && t < max_dist; ++i)
+    {
+        float d = map(o + t * v, mat);
+        if(d < HIT_RATIO * t) return true;
+        t += d * STEP_RATIO;
+    }
+    return false;
+}
+
+/* Checks if a ray starts inside of a distance field object. The march in
+ * intersect() then has to flip the sign of the distance field.
+ */
+bool insideSDF(in vec3 o, in vec3 v)
+{
+    material mat;
+    return map(o + MIN_DIST * v, mat) < 0.0;
+}
@@ -0,0 +1,154 @@
+// The distance field part of the scene
+
+#include "common.glsl"
+#include "rotation.glsl"
+#include "materials.glsl"
+
+// Good resource for finding more building blocks for distance functions:
+// http://www.iquilezles.org/www/articles/distfunctions/distfunctions.htm
+
+/* Basic box distance field.
+ *
+ * Parameters:
+ *  p   Point for which to evaluate the distance field
+ *  b   "Radius" of the box
+ *
+ * Returns:
+ *  Distance to the box from point p.
+ */
+float box(vec3 p, vec3 b)
+{
+    vec3 d = abs(p) - b;
+    return min(max(d.x,max(d.y,d.z)),0.0) + length(max(d,0.0));
+}
+
+/* Each object has a distance function and a material function. The distance
+ * function evaluates the distance field of the object at a given point, and
+ * the material function determines the surface material at a point.
+ */
+
+float blob_distance(vec3 p)
+{
+    vec3 q = p - vec3(-0.5, -2.2 + abs(sin(push_data.time*3.0)), 2.0);
+    return length(q) - 0.8 + sin(10.0*q.x)*sin(10.0*q.y)*sin(10.0*q.z)*0.07;
+}
+
+material blob_material(vec3 p)
+{
+    material mat = makeMaterial(vec4(1.0, 0.5, 0.3, 0.0));
+    return mat;
+}
+
+float sphere_distance(vec3 p)
+{
+    return length(p - vec3(1.5, -1.8, 4.0)) - 1.2;
+}
+
+material sphere_material(vec3 p)
+{
+    material mat = makeMaterial(vec4(0.8, 1.0, 0.8, 1.0));
+    mat.roughness = 0.2;
+    mat.transparency = 0.9;
+    return mat;
+}
+
+float room_distance(vec3 p)
+{
+    return max(
+        -box(p-vec3(0.0,3.0,3.0), vec3(0.5, 0.5, 0.5)),
+        -box(p-vec3(0.0,0.0,0.0), vec3(3.0, 3.0, 6.0))
+    );
+}
+
+material room_material(vec3 p)
+{
+    material mat = makeMaterial(vec4(1.0, 1.0, 1.0, 1.0));
+    if(p.x <= -2.98) mat.color.rgb = vec3(1.0, 0.0, 0.0);
+    else if(p.x >= 2.98) mat.color.rgb = vec3(0.0, 1.0, 0.0);
+    return mat;
+}
+
+float crate_distance(vec3 p)
+{
+    return box(rot_y(p-vec3(-1,-1,5), push_data.time), vec3(1, 2, 1));
+}
+
+material crate_material(vec3 p)
+{
+    material mat = makeMaterial(vec4(1.0, 1.0, 1.0, 1.0));
+
+    vec3 q = rot_y(p-vec3(-1,-1,5), push_data.time) * 0.98;
+    if(fract(q.x + floor(q.y*2.0) * 0.5 + floor(q.z*2.0) * 0.5) < 0.5)
+    {
+        mat.color.rgb = vec3(0.0, 1.0, 1.0);
+    }
+    return mat;
+}
+
+/* The distance function collecting all others.
+ *
+ * Parameters:
+ *  p   The point for which to find the nearest surface
+ *  mat The material of the nearest surface
+ *
+ * Returns:
+ *  The distance to the nearest surface.
+ */
+float map(
+    in vec3 p,
+    out material mat
+){
+    float min_dist = MAX_DIST*2.0;
+    float dist = 0.0;
+
+    dist = blob_distance(p);
+    if(dist < min_dist) {
+        mat = blob_material(p);
+        min_dist = dist;
+    }
+
+    dist = room_distance(p);
+    if(dist < min_dist) {
+        mat = room_material(p);
+        min_dist = dist;
+    }
+
+    dist = crate_distance(p);
+    if(dist < min_dist) {
+        mat = crate_material(p);
+        min_dist = dist;
+    }
+
+    dist = sphere_distance(p);
+    if(dist < min_dist) {
+        mat = sphere_material(p);
+        min_dist = dist;
+    }
+
+    // Add your own objects here!
+
+    return min_dist;
+}
+
+/* Calculates the normal of the surface closest to point p.
+ *
+ * Parameters:
+ *  p   The point where the normal should be calculated
+ *  mat The material information, produced as a byproduct
+ *
+ * Returns:
+ *  The normal of the surface.
+ *
+ * See http://www.iquilezles.org/www/articles/normalsSDF/normalsSDF.htm if
+ * you're interested in how this works.
+ */
+vec3 normal(vec3 p, out material mat)
+{
+    const vec2 k = vec2(1.0, -1.0);
+    return normalize(
+        k.xyy * map(p + k.xyy * EPSILON, mat) +
+        k.yyx * map(p + k.yyx * EPSILON, mat) +
+        k.yxy * map(p + k.yxy * EPSILON, mat) +
+        k.xxx * map(p + k.xxx * EPSILON, mat)
+    );
+}
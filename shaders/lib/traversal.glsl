@@ -0,0 +1,112 @@
+// Walking the BVH of the mesh, stackless with the parent pointers of the nodes
+
+#include "common.glsl"
+#include "intersection.glsl"
+
+uvec3 getIndices(uint node) {
+    Node n = bvh.nodes[node];
+    return uvec3(
+        floatBitsToUint(n.bounding_box[0]),
+        floatBitsToUint(n.bounding_box[1]),
+        floatBitsToUint(n.bounding_box[2])
+    );
+}
+
+// Leaves keep the material of their triangle after the indices
+uint getMaterial(uint node) {
+    return floatBitsToUint(bvh.nodes[node].bounding_box[3]);
+}
+
+bool isLeaf(uint node) {
+    return bvh.nodes[node].is_leaf == 1;
+}
+
+bool testBox(in Ray r, in uint node) {
+    float[6] aabb = bvh.nodes[node].bounding_box;
+    double t1 = (aabb[0] - r.orig.x) * r.dir_inv.x;
+    double t2 = (aabb[1] - r.orig.x) * r.dir_inv.x;
+
+    double tmin = min(t1, t2);
+    double tmax = max(t1, t2);
+
+    t1 = (aabb[2] - r.orig.y) * r.dir_inv.y;
+    t2 = (aabb[3] - r.orig.y) * r.dir_inv.y;
+    tmin = max(tmin, min(t1, t2));
+    tmax = min(tmax, max(t1, t2));
+
+    t1 = (aabb[4] - r.orig.z) * r.dir_inv.z;
+    t2 = (aabb[5] - r.orig.z) * r.dir_inv.z;
+    tmin = max(tmin, min(t1, t2));
+    tmax = min(tmax, max(t1, t2));
+
+    // Boxes around flat meshes have no thickness, touching them is a hit
+    return tmax >= max(tmin, 0.0);
+}
+
+uint getNextNode(uint node, Ray ray) {
+    if(!isLeaf(node) && testBox(ray, node)) {
+        return bvh.nodes[node].left_child;
+    }
+
+    uint root = bvh.nodes.length() - 1;
+    uint ni = node;
+    while(ni < root) {
+        Node n = bvh.nodes[ni];
+        Node parent = bvh.nodes[n.parent];
+        // Leaves have no box, their triangle is tested instead
+        if(ni == parent.left_child
+            && (isLeaf(parent.right_child) || testBox(ray, parent.right_child))) {
+            return parent.right_child;
+        }
+        ni = n.parent;
+    }
+    return UINT_MAX;
+}
+
+/* Walks the BVH to find a triangle hit by the ray.
+ *
+ * Parameters:
+ *  ray         The ray to trace
+ *  max_dist    Hits further away than this are ignored
+ *  any_hit     Stop at the first hit found instead of searching for the
+ *              closest one. Enough for shadow rays.
+ *  dist        Distance to the hit
+ *  hit_node    Leaf node of the hit triangle
+ *
+ * Returns:
+ *  true if a triangle was hit, false otherwise.
+ */
+bool traverseBVH(
+    in Ray ray,
+    in float max_dist,
+    in bool any_hit,
+    out float dist,
+    out uint hit_node
+) {
+    bool hit = false;
+    dist = max_dist;
+    if(bvh.nodes.length() == 0) return false;
+
+    // The root is a leaf when the mesh has a single triangle
+    uint current = bvh.nodes.length() - 1;
+    do {
+        if(isLeaf(current)) {
+            uvec3 idx = getIndices(current);
+            vec3 v0 = vert.vertices[idx.x];
+            vec3 v1 = vert.vertices[idx.y];
+            vec3 v2 = vert.vertices[idx.z];
+            vec3 p;
+            float t;
+            vec2 barycentric;
+
+            if(testIntersection(ray, ray.orig, v0, v1, v2, p, t, barycentric) && t < dist) {
+                hit = true;
+                dist = t;
+                hit_node = current;
+                if(any_hit) return true;
+            }
+        }
+    } while((current = getNextNode(current, ray)) < UINT_MAX);
+
+    return hit;
+}
@@ -0,0 +1,29 @@
+#version 450
+
+layout(push_constant) uniform PushData {
+    // Size of the image rendered to
+    vec2 resolution;
+    // x: intensity of the bloom
+    vec2 parameters;
+} push_data;
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+// The largest level of the bloom pyramid
+layout(set = 0, binding = 1) uniform sampler2D bloom;
+
+layout(location = 0) out vec4 f_color;
+
+void main()
+{
+    vec2 uv = gl_FragCoord.xy / push_data.resolution;
+    vec2 texel = 1.0 / vec2(textureSize(bloom, 0));
+
+    vec3 glow = 0.25 * (
+        texture(bloom, uv + texel * vec2(-0.5, -0.5)).rgb +
+        texture(bloom, uv + texel * vec2(0.5, -0.5)).rgb +
+        texture(bloom, uv + texel * vec2(-0.5, 0.5)).rgb +
+        texture(bloom, uv + texel * vec2(0.5, 0.5)).rgb
+    );
+
+    f_color = vec4(texture(scene, uv).rgb + push_data.parameters.x * glow, 1.0);
+}
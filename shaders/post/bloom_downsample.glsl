@@ -0,0 +1,39 @@
+#version 450
+
+layout(push_constant) uniform PushData {
+    // Size of the image rendered to
+    vec2 resolution;
+    // x: brightness threshold, y: 1 if the threshold should be applied
+    vec2 parameters;
+} push_data;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) out vec4 f_color;
+
+// Keeps only the part of the color brighter than the threshold
+vec3 threshold(vec3 c)
+{
+    float brightness = max(c.r, max(c.g, c.b));
+    return c * max(brightness - push_data.parameters.x, 0.0) / max(brightness, 0.0001);
+}
+
+void main()
+{
+    vec2 uv = gl_FragCoord.xy / push_data.resolution;
+    vec2 texel = 1.0 / vec2(textureSize(source, 0));
+
+    // Four bilinear taps average a 4x4 block of the source
+    vec3 color = 0.25 * (
+        texture(source, uv + texel * vec2(-1.0, -1.0)).rgb +
+        texture(source, uv + texel * vec2(1.0, -1.0)).rgb +
+        texture(source, uv + texel * vec2(-1.0, 1.0)).rgb +
+        texture(source, uv + texel * vec2(1.0, 1.0)).rgb
+    );
+
+    if(push_data.parameters.y > 0.5) {
+        color = threshold(color);
+    }
+
+    f_color = vec4(color, 1.0);
+}
@@ -0,0 +1,37 @@
+#version 450
+
+layout(push_constant) uniform PushData {
+    // Size of the image rendered to
+    vec2 resolution;
+    // Unused
+    vec2 parameters;
+} push_data;
+
+// The next smaller level of the bloom pyramid
+layout(set = 0, binding = 0) uniform sampler2D low;
+// The downsampled image of the same size as this level
+layout(set = 0, binding = 1) uniform sampler2D current;
+
+layout(location = 0) out vec4 f_color;
+
+// 3x3 tent filter, smooths the blocky look of the small levels
+vec3 tent(sampler2D image, vec2 uv)
+{
+    vec2 texel = 1.0 / vec2(textureSize(image, 0));
+    vec3 color = 4.0 * texture(image, uv).rgb;
+    color += 2.0 * texture(image, uv + texel * vec2(-1.0, 0.0)).rgb;
+    color += 2.0 * texture(image, uv + texel * vec2(1.0, 0.0)).rgb;
+    color += 2.0 * texture(image, uv + texel * vec2(0.0, -1.0)).rgb;
+    color += 2.0 * texture(image, uv + texel * vec2(0.0, 1.0)).rgb;
+    color += texture(image, uv + texel * vec2(-1.0, -1.0)).rgb;
+    color += texture(image, uv + texel * vec2(1.0, -1.0)).rgb;
+    color += texture(image, uv + texel * vec2(-1.0, 1.0)).rgb;
+    color += texture(image, uv + texel * vec2(1.0, 1.0)).rgb;
+    return color / 16.0;
+}
+
+void main()
+{
+    vec2 uv = gl_FragCoord.xy / push_data.resolution;
+    f_color = vec4(texture(current, uv).rgb + tent(low, uv), 1.0);
+}
@@ -0,0 +1,26 @@
+#version 450
+
+layout(push_constant) uniform PushData {
+    // Size of the image rendered to
+    vec2 resolution;
+    // x: offset of the red and blue channels in the corners, in pixels
+    vec2 parameters;
+} push_data;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) out vec4 f_color;
+
+void main()
+{
+    vec2 uv = gl_FragCoord.xy / push_data.resolution;
+    // Like a cheap lens, the channels drift apart towards the edges
+    vec2 offset = (uv - 0.5) * 2.0 * push_data.parameters.x / push_data.resolution;
+
+    f_color = vec4(
+        texture(source, uv + offset).r,
+        texture(source, uv).g,
+        texture(source, uv - offset).b,
+        1.0
+    );
+}
@@ -0,0 +1,29 @@
+#version 450
+
+layout(push_constant) uniform PushData {
+    // Size of the image rendered to
+    vec2 resolution;
+    // x: how much of the graded color is used
+    vec2 parameters;
+} push_data;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+// Maps gamma encoded colors to graded ones
+layout(set = 0, binding = 1) uniform sampler3D lut;
+
+layout(location = 0) out vec4 f_color;
+
+void main()
+{
+    vec2 uv = gl_FragCoord.xy / push_data.resolution;
+    vec3 color = texture(source, uv).rgb;
+
+    // LUTs are authored for display colors, so values above 1 are clipped
+    vec3 encoded = pow(clamp(color, 0.0, 1.0), vec3(1.0 / 2.2));
+    float size = float(textureSize(lut, 0).x);
+    // Sample at texel centers, so 0 and 1 hit the first and last entry
+    vec3 coord = encoded * ((size - 1.0) / size) + 0.5 / size;
+    vec3 graded = pow(texture(lut, coord).rgb, vec3(2.2));
+
+    f_color = vec4(mix(color, graded, push_data.parameters.x), 1.0);
+}
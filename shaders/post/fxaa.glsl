@@ -0,0 +1,66 @@
+#version 450
+
+// Simplified FXAA, based on Timothy Lottes' console version
+
+layout(push_constant) uniform PushData {
+    // Size of the image rendered to
+    vec2 resolution;
+    // Unused
+    vec2 parameters;
+} push_data;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) out vec4 f_color;
+
+#define FXAA_REDUCE_MIN (1.0 / 128.0)
+#define FXAA_REDUCE_MUL (1.0 / 8.0)
+#define FXAA_SPAN_MAX 8.0
+
+// Edges are detected on roughly tone mapped luminance, not on HDR values
+float luma(vec3 c)
+{
+    c = c / (1.0 + c);
+    return dot(c, vec3(0.299, 0.587, 0.114));
+}
+
+void main()
+{
+    vec2 texel = 1.0 / push_data.resolution;
+    vec2 uv = gl_FragCoord.xy * texel;
+
+    vec3 rgb_m = texture(source, uv).rgb;
+    float luma_nw = luma(texture(source, uv + texel * vec2(-1.0, -1.0)).rgb);
+    float luma_ne = luma(texture(source, uv + texel * vec2(1.0, -1.0)).rgb);
+    float luma_sw = luma(texture(source, uv + texel * vec2(-1.0, 1.0)).rgb);
+    float luma_se = luma(texture(source, uv + texel * vec2(1.0, 1.0)).rgb);
+    float luma_m = luma(rgb_m);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // Blur along the edge, perpendicular to the luminance gradient
+    vec2 dir = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+
+    float dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, -FXAA_SPAN_MAX, FXAA_SPAN_MAX) * texel;
+
+    vec3 rgb_a = 0.5 * (
+        texture(source, uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture(source, uv + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    vec3 rgb_b = rgb_a * 0.5 + 0.25 * (
+        texture(source, uv - dir * 0.5).rgb +
+        texture(source, uv + dir * 0.5).rgb
+    );
+
+    // The wider blur overshot if it left the local luminance range
+    float luma_b = luma(rgb_b);
+    vec3 color = (luma_b < luma_min || luma_b > luma_max) ? rgb_a : rgb_b;
+
+    f_color = vec4(color, 1.0);
+}
@@ -0,0 +1,22 @@
+#version 450
+
+layout(push_constant) uniform PushData {
+    // Size of the image rendered to
+    vec2 resolution;
+    // x: how much the corners are darkened
+    vec2 parameters;
+} push_data;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) out vec4 f_color;
+
+void main()
+{
+    vec2 uv = gl_FragCoord.xy / push_data.resolution;
+    // 0 in the center, 1 in the corners
+    float dist = length(uv - 0.5) * sqrt(2.0);
+    float vignette = 1.0 - push_data.parameters.x * smoothstep(0.3, 1.0, dist);
+
+    f_color = vec4(texture(source, uv).rgb * vignette, 1.0);
+}
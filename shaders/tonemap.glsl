@@ -0,0 +1,62 @@
+#version 450
+
+// Must match the order of tonemap::Operator
+#define OPERATOR_REINHARD 0
+#define OPERATOR_ACES 1
+#define OPERATOR_UNCHARTED2 2
+
+layout(push_constant) uniform PushData {
+    uint operator;
+    // Linear factor the image is scaled with before tone mapping
+    float exposure;
+    float gamma;
+} push_data;
+
+layout(set = 0, binding = 0) uniform sampler2D hdr_image;
+
+layout(location = 0) out vec4 f_color;
+
+vec3 reinhard(vec3 c)
+{
+    return c / (1.0 + c);
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 c)
+{
+    return clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), 0.0, 1.0);
+}
+
+vec3 hableCurve(vec3 x)
+{
+    const float A = 0.15; // shoulder strength
+    const float B = 0.50; // linear strength
+    const float C = 0.10; // linear angle
+    const float D = 0.20; // toe strength
+    const float E = 0.02; // toe numerator
+    const float F = 0.30; // toe denominator
+    return ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F;
+}
+
+// John Hable's filmic curve from Uncharted 2
+vec3 uncharted2(vec3 c)
+{
+    const float WHITE_POINT = 11.2;
+    const float EXPOSURE_BIAS = 2.0;
+    return hableCurve(c * EXPOSURE_BIAS) / hableCurve(vec3(WHITE_POINT));
+}
+
+void main()
+{
+    vec3 color = texelFetch(hdr_image, ivec2(gl_FragCoord.xy), 0).rgb * push_data.exposure;
+
+    if(push_data.operator == OPERATOR_REINHARD) {
+        color = reinhard(color);
+    } else if(push_data.operator == OPERATOR_ACES) {
+        color = aces(color);
+    } else {
+        color = uncharted2(color);
+    }
+
+    f_color = vec4(pow(clamp(color, 0.0, 1.0), vec3(1.0 / push_data.gamma)), 1.0);
+}
@@ -0,0 +1,44 @@
+use std::f32::consts::PI;
+
+/**
+ * Position and viewing direction of the camera. The shader spans the image
+ * plane from these two vectors, using the world Y axis as up.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: [f32; 3],
+    pub direction: [f32; 3],
+}
+
+impl Camera {
+    /**
+     * The camera swaying slowly in front of the room
+     */
+    pub fn animated(time: f32) -> Camera {
+        let x = time.sin() * 0.1;
+        let z = 1.0 + time.cos() * 0.1;
+        let length = (x * x + z * z).sqrt();
+
+        Camera {
+            position: [0.0, time.sin(), -5.0],
+            direction: [x / length, 0.0, z / length],
+        }
+    }
+
+    /**
+     * Roughly how many pixels wide a sphere appears in an image `width`
+     * pixels wide, with the field of view of getEdges() in
+     * shaders/lib/camera.glsl
+     */
+    pub fn projected_size(&self, center: [f32; 3], radius: f32, width: u32) -> f32 {
+        let d = [
+            center[0] - self.position[0],
+            center[1] - self.position[1],
+            center[2] - self.position[2],
+        ];
+        let distance = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt().max(radius);
+        // The image plane spans sin(FOV / 2) to both sides at distance 1
+        let half_width = (PI / 4.0).sin();
+        radius / distance / half_width * width as f32
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::{ImageResult, RgbaImage};
+
+/**
+ * Writes consecutive frames as numbered PNGs
+ */
+pub struct Capture {
+    directory: PathBuf,
+    frames: usize,
+    next: usize,
+}
+
+impl Capture {
+    pub fn new(directory: &str, frames: usize) -> io::Result<Capture> {
+        fs::create_dir_all(directory)?;
+        Ok(Capture {
+            directory: PathBuf::from(directory),
+            frames,
+            next: 0,
+        })
+    }
+
+    pub fn save(&mut self, image: &RgbaImage) -> ImageResult<PathBuf> {
+        let path = self.directory.join(format!("frame_{:04}.png", self.next));
+        image.save(&path)?;
+        self.next += 1;
+        Ok(path)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.frames
+    }
+}
+
+/**
+ * A file name for a screenshot that does not collide with earlier ones
+ */
+pub fn screenshot_path() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    format!(
+        "screenshot-{}-{:03}.png",
+        now.as_secs(),
+        now.subsec_millis()
+    )
+}
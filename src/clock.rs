@@ -0,0 +1,148 @@
+use std::time::Instant;
+
+/**
+ * How the clock advances between frames
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    // Follows the wall clock
+    RealTime,
+    // Advances by a fixed number of seconds per frame, so animations play the
+    // same way on every run, no matter how long frames take
+    Fixed(f64),
+}
+
+/**
+ * Simulated scene time. It can be paused, scrubbed and sped up or slowed down
+ * independently of the wall clock.
+ */
+pub struct Clock {
+    step: Step,
+    time: f64,
+    speed: f64,
+    paused: bool,
+    // The wall clock time of the last tick, None before the first one
+    last_tick: Option<Instant>,
+}
+
+impl Clock {
+    pub fn new(step: Step) -> Clock {
+        Clock {
+            step,
+            time: 0.0,
+            speed: 1.0,
+            paused: false,
+            last_tick: None,
+        }
+    }
+
+    /**
+     * Advances the clock by one frame and returns the new time. The first
+     * tick returns the start time.
+     */
+    pub fn tick(&mut self) -> f32 {
+        let now = Instant::now();
+        if let Some(last_tick) = self.last_tick {
+            if !self.paused {
+                let delta = match self.step {
+                    Step::RealTime => {
+                        let elapsed = now.duration_since(last_tick);
+                        elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9
+                    }
+                    Step::Fixed(step) => step,
+                };
+                self.time += delta * self.speed;
+            }
+        }
+        self.last_tick = Some(now);
+        self.time()
+    }
+
+    /**
+     * Lets a frame pass without advancing the time, for example while path
+     * traced samples accumulate
+     */
+    pub fn hold(&mut self) -> f32 {
+        self.last_tick = Some(Instant::now());
+        self.time()
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time as f32
+    }
+
+    /**
+     * Jumps to a point in time, also while paused
+     */
+    pub fn set_time(&mut self, time: f64) {
+        self.time = time.max(0.0);
+    }
+
+    pub fn scrub(&mut self, seconds: f64) {
+        let time = self.time + seconds;
+        self.set_time(time);
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /**
+     * Scales how fast time passes, 0.5 plays in slow motion
+     */
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_steps_are_scaled_by_the_speed() {
+        let mut clock = Clock::new(Step::Fixed(0.25));
+        clock.set_speed(2.0);
+        assert_eq!(clock.tick(), 0.0);
+        assert_eq!(clock.tick(), 0.5);
+        assert_eq!(clock.tick(), 1.0);
+    }
+
+    #[test]
+    fn paused_and_held_frames_keep_the_time() {
+        let mut clock = Clock::new(Step::Fixed(0.5));
+        clock.tick();
+        clock.tick();
+        clock.set_paused(true);
+        assert_eq!(clock.tick(), 0.5);
+        assert_eq!(clock.tick(), 0.5);
+
+        clock.set_paused(false);
+        assert_eq!(clock.hold(), 0.5);
+        assert_eq!(clock.hold(), 0.5);
+        assert_eq!(clock.tick(), 1.0);
+    }
+
+    #[test]
+    fn time_does_not_go_below_zero() {
+        let mut clock = Clock::new(Step::Fixed(0.5));
+        clock.scrub(2.0);
+        clock.scrub(-3.0);
+        assert_eq!(clock.time(), 0.0);
+
+        clock.set_time(-1.0);
+        assert_eq!(clock.time(), 0.0);
+        // Scrubbing works while paused
+        clock.set_paused(true);
+        clock.scrub(1.5);
+        assert_eq!(clock.time(), 1.5);
+    }
+}
@@ -0,0 +1,221 @@
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read};
+
+/**
+ * An equirectangular environment map in linear RGB. Rows go from the top of
+ * the sky (+Y) to the bottom.
+ */
+pub struct EnvironmentMap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut byte = [0u8];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn rgbe_to_float([r, g, b, e]: [u8; 4]) -> [f32; 4] {
+    if e == 0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    let f = 2f32.powi(e as i32 - (128 + 8));
+    [r as f32 * f, g as f32 * f, b as f32 * f, 1.0]
+}
+
+/**
+ * Reads one scanline in the adaptive run length encoding, where the four
+ * components are stored one after another
+ */
+fn read_rle_scanline<R: Read>(reader: &mut R, scanline: &mut Vec<[u8; 4]>) -> io::Result<()> {
+    let width = scanline.len();
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = read_byte(reader)? as usize;
+            if count > 128 {
+                let count = count - 128;
+                if x + count > width {
+                    return Err(invalid("run exceeds scanline"));
+                }
+                let value = read_byte(reader)?;
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[component] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(invalid("invalid run length"));
+                }
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[component] = read_byte(reader)?;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Loads a Radiance RGBE (.hdr) file with flat or run length encoded scanlines
+ */
+pub fn load_hdr(path: &str) -> io::Result<EnvironmentMap> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid("not a Radiance HDR file"));
+    }
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("unexpected end of header"));
+        }
+        let l = line.trim();
+        if l.is_empty() {
+            break;
+        }
+        if l.starts_with("FORMAT=") && l != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid("only RGBE pixels are supported"));
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let resolution: Vec<&str> = line.split_whitespace().collect();
+    if resolution.len() != 4 || resolution[0] != "-Y" || resolution[2] != "+X" {
+        return Err(invalid("only -Y +X scanline order is supported"));
+    }
+    let height = resolution[1]
+        .parse::<u32>()
+        .map_err(|_| invalid("invalid height"))?;
+    let width = resolution[3]
+        .parse::<u32>()
+        .map_err(|_| invalid("invalid width"))?;
+    if width == 0 || height == 0 {
+        return Err(invalid("empty image"));
+    }
+
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    let mut scanline = vec![[0u8; 4]; width as usize];
+
+    for _ in 0..height {
+        let mut first = [0u8; 4];
+        reader.read_exact(&mut first)?;
+
+        let is_rle = width >= 8
+            && width < 0x8000
+            && first[0] == 2
+            && first[1] == 2
+            && ((first[2] as u32) << 8 | first[3] as u32) == width;
+
+        if is_rle {
+            read_rle_scanline(&mut reader, &mut scanline)?;
+        } else {
+            scanline[0] = first;
+            for pixel in &mut scanline[1..] {
+                reader.read_exact(pixel)?;
+            }
+        }
+
+        pixels.extend(scanline.iter().cloned().map(rgbe_to_float));
+    }
+
+    Ok(EnvironmentMap {
+        width,
+        height,
+        pixels,
+    })
+}
+
+impl EnvironmentMap {
+    /**
+     * An environment of a single color, used when no map was given
+     */
+    pub fn constant([r, g, b]: [f32; 3]) -> EnvironmentMap {
+        EnvironmentMap {
+            width: 1,
+            height: 1,
+            pixels: vec![[r, g, b, 1.0]],
+        }
+    }
+
+    /**
+     * Builds the tables for importance sampling the map in the shader. Pixels
+     * are weighted by luminance and by the solid angle they cover.
+     *
+     * The layout is:
+     *  - the integral over the whole map, 0 if the map is black
+     *  - the cumulative distribution of the rows (height + 1 entries)
+     *  - the cumulative distribution of the pixels in each row
+     *    (height * (width + 1) entries)
+     *  - the probability density of each pixel (width * height entries)
+     */
+    pub fn distribution(&self) -> Vec<f32> {
+        let (w, h) = (self.width as usize, self.height as usize);
+
+        let weights: Vec<f32> = self
+            .pixels
+            .iter()
+            .enumerate()
+            .map(|(i, [r, g, b, _])| {
+                let theta = PI * ((i / w) as f32 + 0.5) / h as f32;
+                (0.2126 * r + 0.7152 * g + 0.0722 * b) * theta.sin()
+            }).collect();
+
+        let mut conditional = Vec::with_capacity(h * (w + 1));
+        let mut row_sums = Vec::with_capacity(h);
+        for row in weights.chunks(w) {
+            let sum: f32 = row.iter().sum();
+            let mut acc = 0.0;
+            conditional.push(0.0);
+            for weight in row {
+                acc += weight;
+                conditional.push(if sum > 0.0 { acc / sum } else { 0.0 });
+            }
+            // A black row is never picked, but has to stay a valid distribution
+            if sum <= 0.0 {
+                let start = conditional.len() - (w + 1);
+                for (i, c) in conditional[start..].iter_mut().enumerate() {
+                    *c = i as f32 / w as f32;
+                }
+            }
+            row_sums.push(sum);
+        }
+
+        let total: f32 = row_sums.iter().sum();
+        let mut marginal = Vec::with_capacity(h + 1);
+        let mut acc = 0.0;
+        marginal.push(0.0);
+        for sum in &row_sums {
+            acc += sum;
+            marginal.push(if total > 0.0 { acc / total } else { 0.0 });
+        }
+
+        // Density over the unit square of texture coordinates
+        let pdf = weights.iter().map(|weight| {
+            if total > 0.0 {
+                weight * (w * h) as f32 / total
+            } else {
+                0.0
+            }
+        });
+
+        let mut data = Vec::with_capacity(1 + (h + 1) + h * (w + 1) + w * h);
+        data.push(total / (w * h) as f32);
+        data.extend(marginal);
+        data.extend(conditional);
+        data.extend(pdf);
+        data
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::Read;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::time::Duration;
+
+use glsl_to_spirv::{self, ShaderType};
+use notify::{self, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use vulkano::device::Device;
+use vulkano::pipeline::shader::ShaderModule;
+
+use preprocess::preprocess;
+
+/**
+ * Watches the shader directory for changed GLSL files
+ */
+pub struct ShaderWatcher {
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+}
+
+fn is_shader(path: &Path) -> bool {
+    path.extension().map_or(false, |e| e == "glsl")
+}
+
+impl ShaderWatcher {
+    pub fn new(directory: &str) -> notify::Result<ShaderWatcher> {
+        let (tx, events) = channel();
+        // Editors often write a file in several steps, which are merged into one event
+        let mut watcher = notify::watcher(tx, Duration::from_millis(200))?;
+        watcher.watch(directory, RecursiveMode::Recursive)?;
+
+        Ok(ShaderWatcher {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /**
+     * Whether a shader was written, created or removed since the last call
+     */
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter() {
+            changed |= match event {
+                DebouncedEvent::Create(ref path)
+                | DebouncedEvent::Write(ref path)
+                | DebouncedEvent::Remove(ref path) => is_shader(path),
+                DebouncedEvent::Rename(ref from, ref to) => is_shader(from) || is_shader(to),
+                _ => false,
+            };
+        }
+        changed
+    }
+}
+
+/**
+ * Preprocesses and compiles a shader to a new module. Errors contain the
+ * compiler output, with the files the source string numbers refer to.
+ */
+pub fn compile(device: Arc<Device>, path: &str, ty: ShaderType) -> Result<Arc<ShaderModule>, String> {
+    let source = preprocess(Path::new(path)).map_err(|e| e.to_string())?;
+
+    let mut spirv = vec![];
+    glsl_to_spirv::compile(&source.code, ty)
+        .map_err(|log| {
+            let files: Vec<String> = source
+                .files
+                .iter()
+                .enumerate()
+                .map(|(i, f)| format!("  {}: {}", i, f.display()))
+                .collect();
+            format!("{}\nSource strings:\n{}", log.trim(), files.join("\n"))
+        })?.read_to_end(&mut spirv)
+        .map_err(|e| e.to_string())?;
+
+    // The SPIR-V comes straight from glslang, so it is valid
+    unsafe { ShaderModule::new(device, &spirv) }.map_err(|e| e.to_string())
+}
@@ -0,0 +1,28 @@
+extern crate glsl_to_spirv;
+extern crate image;
+extern crate notify;
+#[macro_use]
+extern crate vulkano;
+#[macro_use]
+extern crate vulkano_shader_derive;
+
+pub mod camera;
+pub mod clock;
+pub mod environment;
+pub mod hot_reload;
+pub mod light;
+pub mod material;
+pub mod object;
+pub mod post;
+mod preprocess;
+mod quad;
+pub mod reference;
+pub mod renderer;
+pub mod scene;
+mod shaders;
+pub mod texture;
+mod tiles;
+pub mod tonemap;
+
+pub use renderer::{Renderer, Settings};
+pub use scene::Scene;
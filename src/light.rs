@@ -0,0 +1,179 @@
+const POINT: u32 = 0;
+const SPOT: u32 = 1;
+const DIRECTIONAL: u32 = 2;
+const AREA: u32 = 3;
+
+/**
+ * A light source as it is laid out in the light storage buffer of the shader.
+ * Every vector is padded to four components to match the std430 layout.
+ */
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub struct Light {
+    position: [f32; 4],
+    direction: [f32; 4],
+    color: [f32; 4],
+    edge_u: [f32; 4],
+    edge_v: [f32; 4],
+    kind: u32,
+    cos_inner: f32,
+    cos_outer: f32,
+    padding: f32,
+}
+
+fn pad([x, y, z]: [f32; 3]) -> [f32; 4] {
+    [x, y, z, 0.0]
+}
+
+fn normalize([x, y, z]: [f32; 3]) -> [f32; 3] {
+    let length = (x * x + y * y + z * z).sqrt();
+    [x / length, y / length, z / length]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross([ax, ay, az]: [f32; 3], [bx, by, bz]: [f32; 3]) -> [f32; 3] {
+    [ay * bz - az * by, az * bx - ax * bz, ax * by - ay * bx]
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl Light {
+    fn new(kind: u32, color: [f32; 3], intensity: f32) -> Light {
+        Light {
+            position: [0.0; 4],
+            direction: [0.0; 4],
+            color: [color[0], color[1], color[2], intensity],
+            edge_u: [0.0; 4],
+            edge_v: [0.0; 4],
+            kind,
+            cos_inner: 0.0,
+            cos_outer: 0.0,
+            padding: 0.0,
+        }
+    }
+
+    /**
+     * A light emitting equally in all directions from a single point
+     */
+    pub fn point(position: [f32; 3], color: [f32; 3], intensity: f32) -> Light {
+        Light {
+            position: pad(position),
+            ..Light::new(POINT, color, intensity)
+        }
+    }
+
+    /**
+     * A point light restricted to a cone around `direction`. The intensity
+     * fades out between the inner and the outer angle (in radians).
+     */
+    pub fn spot(
+        position: [f32; 3],
+        direction: [f32; 3],
+        inner_angle: f32,
+        outer_angle: f32,
+        color: [f32; 3],
+        intensity: f32,
+    ) -> Light {
+        Light {
+            position: pad(position),
+            direction: pad(normalize(direction)),
+            cos_inner: inner_angle.cos(),
+            cos_outer: outer_angle.cos(),
+            ..Light::new(SPOT, color, intensity)
+        }
+    }
+
+    /**
+     * A light infinitely far away, like the sun. Its intensity does not
+     * fall off with distance.
+     */
+    pub fn directional(direction: [f32; 3], color: [f32; 3], intensity: f32) -> Light {
+        Light {
+            direction: pad(normalize(direction)),
+            ..Light::new(DIRECTIONAL, color, intensity)
+        }
+    }
+
+    /**
+     * A rectangular light spanned by `edge_u` and `edge_v` from `corner`. It
+     * emits to the side the cross product of the two edges points to.
+     */
+    pub fn area(
+        corner: [f32; 3],
+        edge_u: [f32; 3],
+        edge_v: [f32; 3],
+        color: [f32; 3],
+        intensity: f32,
+    ) -> Light {
+        let normal = normalize(cross(edge_u, edge_v));
+
+        Light {
+            position: pad(corner),
+            direction: pad(normal),
+            edge_u: pad(edge_u),
+            edge_v: pad(edge_v),
+            ..Light::new(AREA, color, intensity)
+        }
+    }
+
+    pub fn is_area(&self) -> bool {
+        self.kind == AREA
+    }
+
+    /**
+     * The radiance arriving at `p` without occlusion, the direction towards
+     * the light and its distance. `xi` picks the point on area lights. Same
+     * as sampleLight() in the shader.
+     */
+    pub fn sample(&self, p: [f32; 3], xi: [f32; 2]) -> ([f32; 3], [f32; 3], f32) {
+        let [r, g, b, intensity] = self.color;
+        let radiance = [r * intensity, g * intensity, b * intensity];
+        let direction = [self.direction[0], self.direction[1], self.direction[2]];
+
+        if self.kind == DIRECTIONAL {
+            let [x, y, z] = direction;
+            return (radiance, [-x, -y, -z], f32::INFINITY);
+        }
+
+        let mut position = [self.position[0], self.position[1], self.position[2]];
+        if self.kind == AREA {
+            for (i, p) in position.iter_mut().enumerate() {
+                *p += xi[0] * self.edge_u[i] + xi[1] * self.edge_v[i];
+            }
+        }
+
+        let to_light = [position[0] - p[0], position[1] - p[1], position[2] - p[2]];
+        let distance = dot(to_light, to_light).sqrt();
+        let light_dir = [
+            to_light[0] / distance,
+            to_light[1] / distance,
+            to_light[2] / distance,
+        ];
+        let mut attenuation = 1.0 / (distance * distance);
+
+        let cos_angle = -dot(light_dir, direction);
+        if self.kind == SPOT {
+            attenuation *= smoothstep(self.cos_outer, self.cos_inner, cos_angle);
+        } else if self.kind == AREA {
+            let u = [self.edge_u[0], self.edge_u[1], self.edge_u[2]];
+            let v = [self.edge_v[0], self.edge_v[1], self.edge_v[2]];
+            let [x, y, z] = cross(u, v);
+            let area = (x * x + y * y + z * z).sqrt();
+            attenuation *= area * cos_angle.max(0.0);
+        }
+
+        let [r, g, b] = radiance;
+        (
+            [r * attenuation, g * attenuation, b * attenuation],
+            light_dir,
+            distance,
+        )
+    }
+}
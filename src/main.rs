@@ -1,40 +1,202 @@
-#[macro_use]
+extern crate coding_assignment;
+extern crate image;
 extern crate vulkano;
-#[macro_use]
-extern crate vulkano_shader_derive;
 extern crate vulkano_win;
 extern crate winit;
 
 use vulkano_win::VkSurfaceBuild;
 
-use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, ImmutableBuffer};
-use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
-use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
 use vulkano::device::Device;
-use vulkano::framebuffer::{Framebuffer, Subpass};
+use vulkano::format::Format;
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract};
 use vulkano::instance::Instance;
-use vulkano::pipeline::viewport::Viewport;
-use vulkano::pipeline::GraphicsPipeline;
 use vulkano::swapchain;
 use vulkano::swapchain::{
     AcquireError, PresentMode, SurfaceTransform, Swapchain, SwapchainCreationError,
 };
 use vulkano::sync::{now, GpuFuture};
 
+use std::mem;
+use std::process;
 use std::sync::Arc;
-use std::time::SystemTime;
 
-mod object;
-mod shaders;
+use coding_assignment::camera::Camera;
+use coding_assignment::clock::{Clock, Step};
+use coding_assignment::hot_reload::ShaderWatcher;
+use coding_assignment::{environment, light, material, object, reference, texture};
+use coding_assignment::{Renderer, Scene, Settings};
+
+mod capture;
+mod options;
+
+const TITLE: &str = "Raytracer";
+
+/**
+ * Writes the levels of detail asked for with --write-lods next to the model
+ * files, placed like the models and without smoothed normals
+ */
+fn write_lods(options: &options::Options) {
+    for model in &options.models {
+        let levels = match model.write_lods {
+            Some(levels) => levels,
+            None => continue,
+        };
+        let (object, _) = object::Model {
+            crease_angle: None,
+            fallback_crease_angle: None,
+            detail: object::Detail::Full,
+            ..model.model.clone()
+        }.load();
+
+        let stem = model.model.path.trim_end_matches(".ply");
+        for (level, lod) in object.levels_of_detail(levels).iter().enumerate() {
+            let path = format!("{}_lod{}.ply", stem, level);
+            lod.save(&path).expect("Failed to save level of detail");
+            println!("Saved {} with {} triangles", path, lod.indices.len());
+        }
+    }
+}
+
+/**
+ * The demo scene: the models with their textures, lit by three lamps. Levels
+ * of detail are picked for an image of the given width.
+ */
+fn load_scene(options: &options::Options, width: u32) -> Scene {
+    let models: Vec<object::Model> = options
+        .models
+        .iter()
+        .enumerate()
+        .map(|(i, model)| object::Model {
+            material: i as u32,
+            detail: match model.auto_lod {
+                Some((levels, pixels_per_triangle)) => object::Detail::Auto {
+                    levels,
+                    camera: Camera::animated(options.start_time as f32),
+                    width,
+                    pixels_per_triangle,
+                },
+                None => model.model.detail,
+            },
+            ..model.model.clone()
+        }).collect();
+    let objects = models
+        .iter()
+        .map(|model| {
+            let (object, report) = model.load();
+            if let Some(clean_up) = report.clean_up {
+                println!("Cleaned up {}: {}", model.path, clean_up);
+            }
+            match report.detail {
+                Some(object::DetailReport::Decimated { triangles, error }) => println!(
+                    "Decimated {} to {} triangles, moving the surface by up to {}",
+                    model.path, triangles, error
+                ),
+                Some(object::DetailReport::Auto {
+                    level,
+                    triangles,
+                    projected_width,
+                }) => println!(
+                    "Picked level {} of {} with {} triangles, {:.0} pixels wide",
+                    level, model.path, triangles, projected_width
+                ),
+                None => {}
+            }
+            object
+        }).collect();
+    let object = object::merge(objects);
+    println!("Loaded {} models", models.len());
+
+    // Every texture given on the command line becomes a layer of the array
+    let mut texture_paths = vec![];
+    let mut texture_layer = |path: &Option<String>| match *path {
+        Some(ref p) => {
+            texture_paths.push(p.clone());
+            texture_paths.len() as i32 - 1
+        }
+        None => -1,
+    };
+
+    let materials = options
+        .models
+        .iter()
+        .map(|model| {
+            let [r, g, b] = model.color;
+            material::Material {
+                color: [r, g, b, 1.0],
+                roughness: 0.3,
+                reflectivity: model.reflectivity,
+                transparency: model.transparency,
+                ior: model.ior,
+                albedo_texture: texture_layer(&model.albedo),
+                normal_texture: texture_layer(&model.normal_map),
+                roughness_texture: texture_layer(&model.roughness_map),
+                ..Default::default()
+            }
+        }).collect();
+
+    let textures = texture::load_textures(&texture_paths).expect("Failed to load textures");
+    println!("Loaded {} textures", texture_paths.len());
 
-#[derive(Clone, Copy)]
-#[allow(dead_code)]
-struct PushData {
-    time: f32,
-    resolution: [u32; 2],
+    let environment = match options.environment {
+        Some(ref path) => {
+            let map = environment::load_hdr(path).expect("Failed to load environment map");
+            println!("Loaded environment map");
+            map
+        }
+        None => environment::EnvironmentMap::constant([0.0, 0.0, 0.0]),
+    };
+
+    let lights = vec![
+        // This lamp is positioned at the hole in the roof.
+        light::Light::point([0.0, 3.1, 3.0], [1.0, 1.0, 1.0], 12.0),
+        light::Light::spot(
+            [2.5, 2.5, -2.0],
+            [-2.5, -2.4, 2.0],
+            0.2,
+            0.35,
+            [1.0, 0.8, 0.5],
+            20.0,
+        ),
+        light::Light::area(
+            [-1.0, 2.95, -1.0],
+            [2.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.6, 0.7, 1.0],
+            4.0,
+        ),
+    ];
+
+    Scene {
+        object,
+        materials,
+        lights,
+        environment,
+        textures,
+    }
 }
 
 fn main() {
+    let options = options::Options::from_args();
+    write_lods(&options);
+
+    if let Some(ref path) = options.reference {
+        let scene = load_scene(&options, options.reference_size[0]);
+        let image = reference::render(
+            Arc::new(scene),
+            reference::Settings {
+                dimensions: options.reference_size,
+                camera: Camera::animated(options.start_time as f32),
+                max_depth: options.max_depth,
+                environment_rotation: options.environment_rotation,
+                tone_mapping: options.tone_mapping,
+                threads: options.threads,
+            },
+        );
+        image.save(path).expect("Failed to save reference image");
+        println!("Saved {}", path);
+        return;
+    }
+
     let instance = {
         let extensions = vulkano_win::required_extensions();
         Instance::new(None, &extensions, None).expect("failed to create Vulkan instance")
@@ -52,6 +214,7 @@ fn main() {
 
     let mut events_loop = winit::EventsLoop::new();
     let surface = winit::WindowBuilder::new()
+        .with_title(TITLE)
         .build_vk_surface(&events_loop, instance.clone())
         .unwrap();
 
@@ -85,7 +248,13 @@ fn main() {
 
         dimensions = caps.current_extent.unwrap_or([1024, 768]);
         let alpha = caps.supported_composite_alpha.iter().next().unwrap();
-        let format = caps.supported_formats[0].0;
+        // Gamma is applied by the tone mapping pass, so a linear format is preferred
+        let format = caps
+            .supported_formats
+            .iter()
+            .map(|f| f.0)
+            .find(|&f| f == Format::B8G8R8A8Unorm || f == Format::R8G8B8A8Unorm)
+            .unwrap_or(caps.supported_formats[0].0);
 
         Swapchain::new(
             device.clone(),
@@ -104,162 +273,65 @@ fn main() {
         ).expect("failed to create swapchain")
     };
 
-    let vertex_buffer = {
-        #[derive(Debug, Clone)]
-        struct Vertex {
-            position: [f32; 2],
-        }
-        impl_vertex!(Vertex, position);
-
-        CpuAccessibleBuffer::from_iter(
-            device.clone(),
-            BufferUsage::all(),
-            [
-                Vertex {
-                    position: [-1.0, -1.0],
-                },
-                Vertex {
-                    position: [1.0, -1.0],
-                },
-                Vertex {
-                    position: [-1.0, 1.0],
-                },
-                Vertex {
-                    position: [1.0, 1.0],
-                },
-                Vertex {
-                    position: [1.0, -1.0],
-                },
-                Vertex {
-                    position: [-1.0, 1.0],
-                },
-            ]
-                .iter()
-                .cloned(),
-        ).expect("failed to create buffer")
-    };
-
-    let vs = shaders::get_vertex_shader(device.clone());
-    let fs = shaders::get_fragment_shader(device.clone());
-
-    let render_pass = Arc::new(
-        single_pass_renderpass!(device.clone(),
-        attachments: {
-            color: { //color is a custom name here
-                load: Clear,
-                store: Store,
-                format: swapchain.format(),
-                samples: 1,
-            }
+    let (mut renderer, post_future) = Renderer::new(
+        device.clone(),
+        queue.clone(),
+        swapchain.format(),
+        dimensions,
+        Settings {
+            post: options.post.clone(),
+            compute: options.compute,
+            workgroup_size: options.workgroup_size,
+            tile_size: options.tile_size,
+            tiles_per_submit: options.tiles_per_submit,
+            tone_mapping: options.tone_mapping,
+            environment_rotation: options.environment_rotation,
+            max_depth: options.max_depth,
         },
-        pass: {
-            color: [color], //here the name is referenced
-            depth_stencil: {}
-        }
-    ).unwrap(),
-    );
-
-    let pipeline = Arc::new(
-        GraphicsPipeline::start()
-            .vertex_input_single_buffer()
-            .vertex_shader(vs.main_entry_point(), ())
-            .triangle_list()
-            .viewports_dynamic_scissors_irrelevant(1)
-            .fragment_shader(fs.main_entry_point(), ())
-            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-            .build(device.clone())
-            .unwrap(),
     );
 
-    let mut framebuffers: Option<Vec<Arc<vulkano::framebuffer::Framebuffer<_, _>>>> = None;
+    let mut framebuffers: Option<Vec<Arc<FramebufferAbstract + Send + Sync>>> = None;
 
     let mut recreate_swapchain = false; //On window resize the swapchain has to be recreated
 
-    let mut dynamic_state = DynamicState {
-        line_width: None,
-        viewports: Some(vec![Viewport {
-            origin: [0.0, 0.0],
-            dimensions: [dimensions[0] as f32, dimensions[1] as f32],
-            depth_range: 0.0..1.0,
-        }]),
-        scissors: None,
-    };
-
-    let mut last_time = SystemTime::now();
-
-    let object = object::load_object("resources/bunny_low_res.ply");
-    println!("Loaded model");
-
-    let mut push_data = PushData {
-        time: 0.0,
-        resolution: dimensions,
-    };
-
-    let mut new_dimensions = dimensions;
-
-    let (vertex_uniform, f1) = ImmutableBuffer::from_iter(
-        object.vertices.into_iter(),
-        BufferUsage {
-            storage_buffer: true,
-            ..BufferUsage::none()
-        },
-        queue.clone(),
-    ).expect("Failed to create vertex uniform buffer");
-
-    let (index_uniform, f2) = ImmutableBuffer::from_iter(
-        object.indices.into_iter(),
-        BufferUsage {
-            storage_buffer: true,
-            ..BufferUsage::none()
-        },
-        queue.clone(),
-    ).expect("Failed to create index uniform buffer");
-
-    let (bvh_uniform, f3) = ImmutableBuffer::from_iter(
-        object.bvh.into_iter(),
-        BufferUsage {
-            storage_buffer: true,
-            ..BufferUsage::none()
-        },
-        queue.clone(),
-    ).expect("Failed to create bvh uniform buffer");
+    let scene = load_scene(&options, dimensions[0]);
+    let scene_future = renderer.load_scene(scene);
 
     let mut previous_frame_end = Box::new(
         now(device.clone())
-            .join(f1)
-            .join(f2)
-            .join(f3)
+            .join(scene_future)
+            .join(post_future)
             .then_signal_fence_and_flush()
             .unwrap(),
     ) as Box<GpuFuture>;
 
-    let set = Arc::new(
-        PersistentDescriptorSet::start(pipeline.clone(), 0)
-            .add_buffer(vertex_uniform.clone())
-            .unwrap()
-            .add_buffer(index_uniform.clone())
-            .unwrap()
-            .add_buffer(bvh_uniform.clone())
-            .unwrap()
-            .build()
-            .unwrap(),
-    );
+    let mut new_dimensions = dimensions;
 
-    loop {
-        let current_time = SystemTime::now();
-        let delta_time = current_time
-            .duration_since(last_time)
-            .unwrap()
-            .subsec_nanos() as f32
-            / 10.0e8;
-        let new_time = push_data.time + delta_time;
-        if new_time == push_data.time {
-            push_data.time = 0.0;
-        } else {
-            push_data.time = new_time;
+    let mut capture = options.capture.map(|frames| {
+        capture::Capture::new(&options.capture_dir, frames)
+            .expect("Failed to create capture directory")
+    });
+
+    // Captured frames are evenly spaced in scene time, however long they take
+    let mut clock = Clock::new(if capture.is_some() {
+        Step::Fixed(1.0 / f64::from(options.capture_fps))
+    } else {
+        options.step
+    });
+    clock.set_time(options.start_time);
+    clock.set_speed(options.speed);
+    clock.set_paused(options.paused);
+    let mut take_screenshot = false;
+
+    let shader_watcher = match ShaderWatcher::new("shaders") {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            println!("Not watching shaders for changes: {}", e);
+            None
         }
-        last_time = current_time;
+    };
 
+    loop {
         previous_frame_end.cleanup_finished();
 
         if recreate_swapchain {
@@ -274,7 +346,6 @@ fn main() {
         }
         if recreate_swapchain {
             println!("new size: {}, {}", dimensions[0], dimensions[1]);
-            push_data.resolution = dimensions;
 
             let (new_swapchain, new_images) = match swapchain.recreate_with_dimension(dimensions) {
                 Ok(r) => r,
@@ -288,28 +359,37 @@ fn main() {
             images = new_images;
 
             framebuffers = None;
-
-            dynamic_state.viewports = Some(vec![Viewport {
-                origin: [0.0, 0.0],
-                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
-                depth_range: 0.0..1.0,
-            }]);
+            renderer.resize(dimensions);
 
             recreate_swapchain = false;
         }
 
+        if shader_watcher.as_ref().map_or(false, |w| w.changed()) {
+            renderer.reload_shaders();
+        }
+
+        if renderer.needs_frame() {
+            // The scene is frozen while path tracing, so samples can accumulate
+            let time = if renderer.path_tracing() {
+                clock.hold()
+            } else {
+                clock.tick()
+            };
+            renderer.start_frame(Camera::animated(time), time);
+        }
+
         if framebuffers.is_none() {
             framebuffers = Some(
                 images
                     .iter()
                     .map(|image| {
                         Arc::new(
-                            Framebuffer::start(render_pass.clone())
+                            Framebuffer::start(renderer.output_pass())
                                 .add(image.clone())
                                 .unwrap()
                                 .build()
                                 .unwrap(),
-                        )
+                        ) as Arc<FramebufferAbstract + Send + Sync>
                     }).collect::<Vec<_>>(),
             );
         }
@@ -325,24 +405,18 @@ fn main() {
             };
 
         let command_buffer =
-            AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue.family())
-                .unwrap()
-                .begin_render_pass(
-                    framebuffers.as_ref().unwrap()[image_num].clone(),
-                    false,
-                    vec![[0.0, 0.0, 1.0, 1.0].into()],
-                ).unwrap()
-                .draw(
-                    pipeline.clone(),
-                    &dynamic_state,
-                    vertex_buffer.clone(),
-                    set.clone(),
-                    push_data,
-                ).unwrap()
-                .end_render_pass()
-                .unwrap()
-                .build()
-                .unwrap();
+            renderer.render_frame(framebuffers.as_ref().unwrap()[image_num].clone());
+
+        if let Some(progress) = renderer.progress() {
+            let status = if renderer.is_paused() {
+                "cancelled".to_string()
+            } else {
+                format!("{:.0}%", progress * 100.0)
+            };
+            surface
+                .window()
+                .set_title(&format!("{} - {}", TITLE, status));
+        }
 
         let future = previous_frame_end
             .join(acquire_future)
@@ -365,6 +439,39 @@ fn main() {
             }
         }
 
+        // A frame is captured once its last tile was submitted
+        let frame_done = renderer.needs_frame();
+        if take_screenshot || (capture.is_some() && frame_done) {
+            let previous = mem::replace(
+                &mut previous_frame_end,
+                Box::new(now(device.clone())) as Box<GpuFuture>,
+            );
+            let image = renderer.screenshot(previous);
+
+            if take_screenshot {
+                let path = capture::screenshot_path();
+                match image.save(&path) {
+                    Ok(()) => println!("Saved {}", path),
+                    Err(e) => println!("Failed to save {}: {}", path, e),
+                }
+                take_screenshot = false;
+            }
+            if let Some(ref mut capture) = capture {
+                if frame_done {
+                    match capture.save(&image) {
+                        Ok(path) => println!("Saved {}", path.display()),
+                        Err(e) => {
+                            println!("Failed to save frame: {}", e);
+                            process::exit(1);
+                        }
+                    }
+                }
+            }
+        }
+        if capture.as_ref().map_or(false, |c| c.is_done()) {
+            return;
+        }
+
         let mut done = false;
         events_loop.poll_events(|ev| match ev {
             winit::Event::WindowEvent {
@@ -378,6 +485,88 @@ fn main() {
                 let (w, h): (u32, u32) = size.into();
                 new_dimensions = [w, h];
             }
+            winit::Event::WindowEvent {
+                event:
+                    winit::WindowEvent::KeyboardInput {
+                        input:
+                            winit::KeyboardInput {
+                                state: winit::ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => match key {
+                winit::VirtualKeyCode::P => {
+                    let path_tracing = !renderer.path_tracing();
+                    renderer.set_path_tracing(path_tracing);
+                }
+                // Stops tracing, the last image stays on screen until it is pressed again
+                winit::VirtualKeyCode::Escape => {
+                    let paused = !renderer.is_paused();
+                    renderer.set_paused(paused);
+                }
+                winit::VirtualKeyCode::F12 => take_screenshot = true,
+                winit::VirtualKeyCode::C => {
+                    let compute = !renderer.compute();
+                    renderer.set_compute(compute);
+                    println!(
+                        "Tracing in the {} shader",
+                        if compute { "compute" } else { "fragment" }
+                    );
+                }
+                winit::VirtualKeyCode::Space => {
+                    let paused = !clock.is_paused();
+                    clock.set_paused(paused);
+                    println!("Time {}", if paused { "paused" } else { "running" });
+                }
+                winit::VirtualKeyCode::Comma => clock.scrub(-0.1),
+                winit::VirtualKeyCode::Period => clock.scrub(0.1),
+                winit::VirtualKeyCode::Up => {
+                    let speed = clock.speed() * 2.0;
+                    clock.set_speed(speed);
+                    println!("Time speed {}x", speed);
+                }
+                winit::VirtualKeyCode::Down => {
+                    let speed = clock.speed() / 2.0;
+                    clock.set_speed(speed);
+                    println!("Time speed {}x", speed);
+                }
+                winit::VirtualKeyCode::Left => renderer.rotate_environment(-0.1),
+                winit::VirtualKeyCode::Right => renderer.rotate_environment(0.1),
+                winit::VirtualKeyCode::T => {
+                    renderer.tone_mapping.operator = renderer.tone_mapping.operator.next();
+                    println!("{:?}", renderer.tone_mapping);
+                }
+                winit::VirtualKeyCode::Minus => {
+                    renderer.tone_mapping.exposure -= 0.5;
+                    println!("{:?}", renderer.tone_mapping);
+                }
+                winit::VirtualKeyCode::Equals => {
+                    renderer.tone_mapping.exposure += 0.5;
+                    println!("{:?}", renderer.tone_mapping);
+                }
+                winit::VirtualKeyCode::Key9 => {
+                    let max_depth = renderer.max_depth().saturating_sub(1);
+                    renderer.set_max_depth(max_depth);
+                    println!("Maximum ray depth {}", max_depth);
+                }
+                winit::VirtualKeyCode::Key0 => {
+                    let max_depth = renderer.max_depth() + 1;
+                    renderer.set_max_depth(max_depth);
+                    println!("Maximum ray depth {}", max_depth);
+                }
+                winit::VirtualKeyCode::LBracket => {
+                    renderer.tone_mapping.gamma = (renderer.tone_mapping.gamma - 0.1).max(0.1);
+                    println!("{:?}", renderer.tone_mapping);
+                }
+                winit::VirtualKeyCode::RBracket => {
+                    renderer.tone_mapping.gamma += 0.1;
+                    println!("{:?}", renderer.tone_mapping);
+                }
+                _ => (),
+            },
             _ => (),
         });
         if done {
@@ -0,0 +1,34 @@
+/**
+ * Surface properties of a mesh as they are laid out in the material storage
+ * buffer of the shader. Texture indices refer to layers of the texture array,
+ * -1 means the surface is not textured.
+ */
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Material {
+    pub color: [f32; 4],
+    pub roughness: f32,
+    pub specular: f32,
+    pub reflectivity: f32,
+    pub transparency: f32,
+    pub ior: f32,
+    pub albedo_texture: i32,
+    pub normal_texture: i32,
+    pub roughness_texture: i32,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            color: [1.0, 1.0, 1.0, 1.0],
+            roughness: 0.5,
+            specular: 0.04,
+            reflectivity: 0.0,
+            transparency: 0.0,
+            ior: 1.5,
+            albedo_texture: -1,
+            normal_texture: -1,
+            roughness_texture: -1,
+        }
+    }
+}
@@ -44,6 +44,36 @@ pub struct Node {
     is_leaf: bool,
 }
 
+impl Node {
+    /**
+     * The indices of the triangle in a leaf, None for inner nodes
+     */
+    pub fn triangle(&self) -> Option<[u32; 4]> {
+        if self.is_leaf {
+            Some(unsafe { self.data.leaf })
+        } else {
+            None
+        }
+    }
+
+    /**
+     * The bounding box as [x_min, x_max, y_min, y_max, z_min, z_max] and
+     * the indices of the two children of an inner node, None for leaves
+     */
+    pub fn children(&self) -> Option<([f32; 6], u32, u32)> {
+        if self.is_leaf {
+            None
+        } else {
+            let node = unsafe { self.data.node };
+            Some((node.bounding_box, node.left_child, node.right_child))
+        }
+    }
+
+    pub fn parent(&self) -> u32 {
+        self.parent
+    }
+}
+
 fn expand_bits(v: u32) -> u32 {
     let mut x = (Wrapping(v) * Wrapping(0x00010001u32)) & Wrapping(0xFF0000FFu32);
     x = (x * Wrapping(0x00000101u32)) & Wrapping(0x0F00F00Fu32);
@@ -74,8 +104,43 @@ enum BVH {
     Node(Box<BVH>, Box<BVH>),
 }
 
-// uses LBVH algorithm
+/**
+ * Builds the BVH of a mesh with the LBVH algorithm. The root is the last node.
+ * A mesh without triangles has no nodes and one with a single triangle only
+ * has a leaf.
+ */
 pub fn construct_bvh(vertices: &Vec<[f32; 4]>, indices: &Vec<[u32; 4]>) -> Vec<Node> {
+    if indices.is_empty() {
+        return Vec::new();
+    }
+
+    // The triangles have to be sorted together with their codes
+    let mut sorted: Vec<(Morton, [u32; 4])> = morton_codes(vertices, indices)
+        .into_iter()
+        .zip(indices.iter().cloned())
+        .collect();
+    sorted.sort_unstable();
+    let morton_slice: Vec<Morton> = sorted.iter().map(|&(code, _)| code).collect();
+    let indices: Vec<[u32; 4]> = sorted.into_iter().map(|(_, t)| t).collect();
+
+    let tree = generate_hierarchy(&morton_slice, &indices, 0, morton_slice.len() - 1);
+
+    let mut flat_tree: Vec<Node> = Vec::new();
+    flat_tree.reserve_exact(indices.len() * 2 - 1);
+
+    flatten_tree(&mut flat_tree, &tree, vertices);
+    let idx = flat_tree.len() - 1 as usize;
+
+    if !flat_tree[idx].is_leaf {
+        set_parents(&mut flat_tree, idx);
+    }
+
+    flat_tree
+}
+
+// The Morton code of the center of every triangle, within the bounds of the
+// mesh
+fn morton_codes(vertices: &Vec<[f32; 4]>, indices: &Vec<[u32; 4]>) -> Vec<Morton> {
     let (xmin, xmax, ymin, ymax, zmin, zmax) = (&vertices).into_iter().fold(
         (
             INFINITY,
@@ -97,7 +162,9 @@ pub fn construct_bvh(vertices: &Vec<[f32; 4]>, indices: &Vec<[u32; 4]>) -> Vec<N
         },
     );
 
-    let (x_length, y_length, z_length) = (xmax - xmin, ymax - ymin, zmax - zmin);
+    // A flat mesh has no extent on some axis, all centers are at 0 there
+    let scale = |length: f32| if length > 0.0 { 1.0 / length } else { 0.0 };
+    let (x_scale, y_scale, z_scale) = (scale(xmax - xmin), scale(ymax - ymin), scale(zmax - zmin));
 
     let centers = indices.into_iter().map(|[x, y, z, _]| {
         let i = *x as usize;
@@ -114,30 +181,16 @@ pub fn construct_bvh(vertices: &Vec<[f32; 4]>, indices: &Vec<[u32; 4]>) -> Vec<N
         [(xm + xx) / 2.0, (ym + yy) / 2.0, (zm + zz) / 2.0]
     });
 
-    let mut morton_codes = Vec::from_iter(
+    Vec::from_iter(
         centers
             .map(|[x, y, z]| {
                 [
-                    (x - xmin) / x_length,
-                    (y - ymin) / y_length,
-                    (z - zmin) / z_length,
+                    (x - xmin) * x_scale,
+                    (y - ymin) * y_scale,
+                    (z - zmin) * z_scale,
                 ]
             }).map(morton_3d),
-    );
-    let morton_slice = &mut morton_codes[..];
-    morton_slice.sort_unstable();
-
-    let tree = generate_hierarchy(morton_slice, indices, 0, morton_slice.len() - 1);
-
-    let mut flat_tree: Vec<Node> = Vec::new();
-    flat_tree.reserve_exact(indices.len() * 2 - 1);
-
-    flatten_tree(&mut flat_tree, &tree, vertices);
-    let idx = flat_tree.len() - 1 as usize;
-
-    set_parents(&mut flat_tree, idx);
-
-    flat_tree
+    )
 }
 
 fn set_parents(vec: &mut Vec<Node>, index: usize) {
@@ -279,3 +332,423 @@ fn find_split(zorder: &[Morton], first: usize, last: usize) -> usize {
         split
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift64*, so failures can be reproduced from the seed
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as u32
+        }
+
+        fn float(&mut self, min: f32, max: f32) -> f32 {
+            min + (max - min) * (self.next() as f32 / u32::max_value() as f32)
+        }
+
+        fn point(&mut self) -> [f32; 3] {
+            [
+                self.float(-1.0, 1.0),
+                self.float(-1.0, 1.0),
+                self.float(-1.0, 1.0),
+            ]
+        }
+    }
+
+    type Mesh = (Vec<[f32; 4]>, Vec<[u32; 4]>);
+
+    // The fourth index identifies the triangle, to find duplicates
+    fn soup(points: Vec<[f32; 3]>) -> Mesh {
+        let vertices = points.iter().map(|&[x, y, z]| [x, y, z, 0.0]).collect();
+        let indices = (0..points.len() as u32 / 3)
+            .map(|i| [i * 3, i * 3 + 1, i * 3 + 2, i])
+            .collect();
+        (vertices, indices)
+    }
+
+    fn random_soup(rng: &mut Rng, triangles: usize) -> Mesh {
+        soup((0..triangles * 3).map(|_| rng.point()).collect())
+    }
+
+    fn compact_bits(x: u32) -> u32 {
+        (0..10).fold(0, |v, i| v | ((x >> (3 * i)) & 1) << i)
+    }
+
+    fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+    }
+
+    fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    }
+
+    fn vertex(vertices: &[[f32; 4]], i: u32) -> [f32; 3] {
+        let [x, y, z, _] = vertices[i as usize];
+        [x, y, z]
+    }
+
+    // Möller-Trumbore, like testIntersection() in the shader
+    fn intersect(o: [f32; 3], d: [f32; 3], vertices: &[[f32; 4]], t: [u32; 4]) -> Option<f32> {
+        let v0 = vertex(vertices, t[0]);
+        let edge1 = sub(vertex(vertices, t[1]), v0);
+        let edge2 = sub(vertex(vertices, t[2]), v0);
+        let h = cross(d, edge2);
+        let a = dot(edge1, h);
+        if a.abs() < 0.00001 {
+            return None;
+        }
+        let f = 1.0 / a;
+        let s = sub(o, v0);
+        let u = f * dot(s, h);
+        let q = cross(s, edge1);
+        let v = f * dot(d, q);
+        let t = f * dot(edge2, q);
+        if u < 0.0 || u > 1.0 || v < 0.0 || u + v > 1.0 || t <= 0.00001 {
+            None
+        } else {
+            Some(t)
+        }
+    }
+
+    // Same comparisons as testBox() in the shader
+    fn hits_box(o: [f32; 3], d: [f32; 3], aabb: [f32; 6]) -> bool {
+        let mut tmin = NEG_INFINITY;
+        let mut tmax = INFINITY;
+        for axis in 0..3 {
+            let t1 = (aabb[2 * axis] - o[axis]) / d[axis];
+            let t2 = (aabb[2 * axis + 1] - o[axis]) / d[axis];
+            tmin = tmin.max(t1.min(t2));
+            tmax = tmax.min(t1.max(t2));
+        }
+        tmax >= tmin.max(0.0)
+    }
+
+    fn closest_hit_bvh(
+        bvh: &[Node],
+        vertices: &[[f32; 4]],
+        o: [f32; 3],
+        d: [f32; 3],
+    ) -> Option<f32> {
+        let mut closest: Option<f32> = None;
+        let mut stack = vec![bvh.len() - 1];
+        while let Some(i) = stack.pop() {
+            match (bvh[i].triangle(), bvh[i].children()) {
+                (Some(t), _) => {
+                    if let Some(dist) = intersect(o, d, vertices, t) {
+                        closest = Some(closest.map_or(dist, |c| c.min(dist)));
+                    }
+                }
+                (None, Some((aabb, left, right))) => {
+                    if hits_box(o, d, aabb) {
+                        stack.push(left as usize);
+                        stack.push(right as usize);
+                    }
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+        closest
+    }
+
+    fn closest_hit_brute_force(mesh: &Mesh, o: [f32; 3], d: [f32; 3]) -> Option<f32> {
+        mesh.1
+            .iter()
+            .filter_map(|&t| intersect(o, d, &mesh.0, t))
+            .fold(None, |closest, dist| {
+                Some(closest.map_or(dist, |c: f32| c.min(dist)))
+            })
+    }
+
+    // Asserts the invariants the shader relies on
+    fn check(mesh: &Mesh, rng: &mut Rng) {
+        let (ref vertices, ref indices) = *mesh;
+        let bvh = construct_bvh(vertices, indices);
+
+        assert_eq!(
+            bvh.len(),
+            indices.len() * 2 - 1,
+            "a binary tree over the triangles"
+        );
+        let root = bvh.len() - 1;
+
+        let mut leaves = vec![];
+        let mut stack = vec![root];
+        while let Some(i) = stack.pop() {
+            assert!(i < bvh.len(), "node {} out of bounds", i);
+            if let Some(triangle) = bvh[i].triangle() {
+                leaves.push(triangle);
+                continue;
+            }
+            let (aabb, left, right) = bvh[i].children().unwrap();
+            for &child in &[left, right] {
+                assert_eq!(
+                    bvh[child as usize].parent(),
+                    i as u32,
+                    "parent of {}",
+                    child
+                );
+                stack.push(child as usize);
+            }
+
+            // Everything below a node is inside its box
+            let mut below = vec![i];
+            while let Some(j) = below.pop() {
+                match bvh[j].children() {
+                    Some((_, l, r)) => below.extend(&[l as usize, r as usize]),
+                    None => {
+                        for &v in &bvh[j].triangle().unwrap()[..3] {
+                            let p = vertex(vertices, v);
+                            for axis in 0..3 {
+                                assert!(
+                                    aabb[2 * axis] <= p[axis] && p[axis] <= aabb[2 * axis + 1],
+                                    "vertex {:?} outside the box {:?} of node {}",
+                                    p,
+                                    aabb,
+                                    i
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Every triangle is reachable exactly once
+        let mut expected = indices.clone();
+        expected.sort();
+        leaves.sort();
+        assert_eq!(leaves, expected);
+
+        for _ in 0..50 {
+            let o = [
+                rng.float(-3.0, 3.0),
+                rng.float(-3.0, 3.0),
+                rng.float(-3.0, 3.0),
+            ];
+            // Aimed inside a random triangle, so most rays hit something
+            // without grazing the corners of the boxes
+            let t = indices[rng.next() as usize % indices.len()];
+            let (u, v) = (rng.float(0.1, 0.45), rng.float(0.1, 0.45));
+            let v0 = vertex(vertices, t[0]);
+            let e1 = sub(vertex(vertices, t[1]), v0);
+            let e2 = sub(vertex(vertices, t[2]), v0);
+            let target = [
+                v0[0] + u * e1[0] + v * e2[0],
+                v0[1] + u * e1[1] + v * e2[1],
+                v0[2] + u * e1[2] + v * e2[2],
+            ];
+            let d = sub(target, o);
+            assert_eq!(
+                closest_hit_bvh(&bvh, vertices, o, d),
+                closest_hit_brute_force(mesh, o, d),
+                "ray from {:?} along {:?}",
+                o,
+                d
+            );
+        }
+    }
+
+    #[test]
+    fn expand_bits_spreads_every_bit() {
+        let mut rng = Rng(1);
+        for _ in 0..1000 {
+            let v = rng.next() % 1024;
+            let x = expand_bits(v);
+            assert_eq!(x & !0x0924_9249, 0, "only every third bit is set");
+            assert_eq!(compact_bits(x), v);
+        }
+    }
+
+    #[test]
+    fn morton_3d_interleaves_the_axes() {
+        let mut rng = Rng(2);
+        for _ in 0..1000 {
+            let p = [
+                rng.float(0.0, 1.0),
+                rng.float(0.0, 1.0),
+                rng.float(0.0, 1.0),
+            ];
+            let Morton(code) = morton_3d(p);
+            assert!(code < 1 << 30);
+            for axis in 0..3 {
+                let expected = (p[axis] * 1024.0).min(1023.0) as u32;
+                assert_eq!(compact_bits(code >> (2 - axis)), expected, "axis {}", axis);
+            }
+        }
+    }
+
+    #[test]
+    fn find_split_splits_at_the_highest_differing_bit() {
+        let mut rng = Rng(3);
+        for _ in 0..1000 {
+            let len = 2 + rng.next() as usize % 30;
+            // Few distinct bits, so there are duplicates and long shared prefixes
+            let mut codes: Vec<Morton> = (0..len).map(|_| Morton(rng.next() % 64)).collect();
+            codes.sort_unstable();
+            let split = find_split(&codes, 0, len - 1);
+            assert!(split < len - 1);
+
+            let (first, last) = (codes[0].0, codes[len - 1].0);
+            if first == last {
+                assert_eq!(split, (len - 1) / 2);
+            } else {
+                let bit = 31 - (first ^ last).leading_zeros();
+                assert!(codes[..=split].iter().all(|c| c.0 >> bit & 1 == 0));
+                assert!(codes[split + 1..].iter().all(|c| c.0 >> bit & 1 == 1));
+            }
+        }
+    }
+
+    #[test]
+    fn generate_hierarchy_keeps_every_triangle() {
+        fn collect(tree: &BVH, leaves: &mut Vec<[u32; 4]>) {
+            match *tree {
+                BVH::Leaf(t) => leaves.push(t),
+                BVH::Node(ref l, ref r) => {
+                    collect(l, leaves);
+                    collect(r, leaves);
+                }
+            }
+        }
+
+        let mut rng = Rng(4);
+        for _ in 0..100 {
+            let triangles = 1 + rng.next() as usize % 50;
+            let (_, indices) = random_soup(&mut rng, triangles);
+            let mut codes: Vec<Morton> = indices.iter().map(|_| Morton(rng.next() % 256)).collect();
+            codes.sort_unstable();
+
+            let mut leaves = vec![];
+            collect(
+                &generate_hierarchy(&codes, &indices, 0, codes.len() - 1),
+                &mut leaves,
+            );
+            leaves.sort();
+            assert_eq!(leaves, indices);
+        }
+    }
+
+    #[test]
+    fn leaves_follow_the_morton_order() {
+        // Leaves from left to right, which generate_hierarchy() builds over
+        // the triangles sorted by their codes
+        fn collect(bvh: &[Node], i: usize, leaves: &mut Vec<[u32; 4]>) {
+            match (bvh[i].triangle(), bvh[i].children()) {
+                (Some(t), _) => leaves.push(t),
+                (None, Some((_, left, right))) => {
+                    collect(bvh, left as usize, leaves);
+                    collect(bvh, right as usize, leaves);
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+
+        let mut rng = Rng(8);
+        for _ in 0..50 {
+            let triangles = 2 + rng.next() as usize % 100;
+            let (vertices, indices) = random_soup(&mut rng, triangles);
+            let bvh = construct_bvh(&vertices, &indices);
+
+            let mut leaves = vec![];
+            collect(&bvh, bvh.len() - 1, &mut leaves);
+            let codes = morton_codes(&vertices, &leaves);
+            assert!(codes.windows(2).all(|w| w[0] <= w[1]));
+        }
+    }
+
+    #[test]
+    fn random_soups() {
+        let mut rng = Rng(5);
+        for _ in 0..50 {
+            let triangles = 2 + rng.next() as usize % 100;
+            let mesh = random_soup(&mut rng, triangles);
+            check(&mesh, &mut rng);
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles() {
+        let mut rng = Rng(6);
+        for _ in 0..20 {
+            let mut points = vec![];
+            for _ in 0..20 {
+                let a = rng.point();
+                let b = rng.point();
+                // A line and a point, both without area
+                points.extend(&[
+                    a,
+                    b,
+                    [
+                        (a[0] + b[0]) / 2.0,
+                        (a[1] + b[1]) / 2.0,
+                        (a[2] + b[2]) / 2.0,
+                    ],
+                ]);
+                points.extend(&[a, a, a]);
+            }
+            points.extend((0..30).map(|_| rng.point()));
+            check(&soup(points), &mut rng);
+        }
+    }
+
+    #[test]
+    fn duplicate_triangles() {
+        let mut rng = Rng(7);
+        for _ in 0..20 {
+            let triangle = [rng.point(), rng.point(), rng.point()];
+            let mut points = vec![];
+            for _ in 0..10 {
+                points.extend(&triangle);
+            }
+            points.extend((0..15).map(|_| rng.point()));
+            check(&soup(points), &mut rng);
+        }
+    }
+
+    #[test]
+    fn coincident_triangles() {
+        let mut rng = Rng(8);
+        let p = rng.point();
+        check(&soup(vec![p; 30]), &mut rng);
+    }
+
+    // A flat mesh has boxes without extent on one axis
+    #[test]
+    fn coplanar_triangles() {
+        let mut rng = Rng(9);
+        for _ in 0..20 {
+            let points = (0..60)
+                .map(|_| [rng.float(-1.0, 1.0), 0.5, rng.float(-1.0, 1.0)])
+                .collect();
+            check(&soup(points), &mut rng);
+        }
+    }
+
+    #[test]
+    fn single_triangle() {
+        let mut rng = Rng(10);
+        for _ in 0..20 {
+            let mesh = random_soup(&mut rng, 1);
+            check(&mesh, &mut rng);
+        }
+    }
+
+    #[test]
+    fn empty_mesh() {
+        assert!(construct_bvh(&vec![], &vec![]).is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::Object;
+
+// Vertices are only welded when their texture coordinates and normals match
+// this closely, so seams of the texture and hard edges stay intact
+const UV_TOLERANCE: f32 = 0.0001;
+const NORMAL_TOLERANCE: f32 = 0.001;
+
+/**
+ * What a cleanup removed from a mesh
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub welded_vertices: usize,
+    pub degenerate_triangles: usize,
+    pub duplicate_triangles: usize,
+    pub unused_vertices: usize,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "welded {} vertices, removed {} degenerate and {} duplicate triangles and {} unused vertices",
+            self.welded_vertices,
+            self.degenerate_triangles,
+            self.duplicate_triangles,
+            self.unused_vertices
+        )
+    }
+}
+
+/**
+ * Welds vertices closer than the tolerance, then drops triangles thinner than
+ * the tolerance, triangles using the same vertices as an earlier one and
+ * vertices no triangle uses. The BVH has to be built again afterwards.
+ */
+pub fn clean_up(object: &mut Object, tolerance: f32) -> Report {
+    let mut report = Report::default();
+
+    let remap = weld(object, tolerance);
+    for t in &mut object.indices {
+        for i in &mut t[..3] {
+            *i = remap[*i as usize];
+        }
+    }
+    report.welded_vertices = (0..remap.len()).filter(|&i| remap[i] as usize != i).count();
+
+    let triangles = object.indices.len();
+    let keep: Vec<bool> = object
+        .indices
+        .iter()
+        .map(|t| !is_degenerate(&object.vertices, t, tolerance))
+        .collect();
+    object.retain_triangles(&keep);
+    report.degenerate_triangles = triangles - object.indices.len();
+
+    // The same vertices in any order, turned around or not
+    let triangles = object.indices.len();
+    let mut seen = HashSet::new();
+    let keep: Vec<bool> = object
+        .indices
+        .iter()
+        .map(|t| {
+            let mut key = [t[0], t[1], t[2]];
+            key.sort();
+            seen.insert(key)
+        }).collect();
+    object.retain_triangles(&keep);
+    report.duplicate_triangles = triangles - object.indices.len();
+
+    // Welded vertices are not used any more either
+    report.unused_vertices = remove_unused_vertices(object) - report.welded_vertices;
+
+    report
+}
+
+// Cell of the grid the vertices are sorted into, a tolerance of 0 only
+// welds vertices at exactly the same position
+fn cell(p: [f32; 4], tolerance: f32) -> [i64; 3] {
+    if tolerance > 0.0 {
+        [
+            (p[0] / tolerance).floor() as i64,
+            (p[1] / tolerance).floor() as i64,
+            (p[2] / tolerance).floor() as i64,
+        ]
+    } else {
+        [
+            i64::from(p[0].to_bits()),
+            i64::from(p[1].to_bits()),
+            i64::from(p[2].to_bits()),
+        ]
+    }
+}
+
+// The vertex every vertex is welded to, the first one of a cluster is kept
+fn weld(object: &Object, tolerance: f32) -> Vec<u32> {
+    let vertices = &object.vertices;
+    let uv = |i: usize| object.uvs.get(i).cloned().unwrap_or([0.0, 0.0]);
+    let normal = |i: usize| object.normals.get(i).cloned().unwrap_or([0.0; 4]);
+    let reach = if tolerance > 0.0 { 1 } else { 0 };
+
+    let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+    let mut remap = Vec::with_capacity(vertices.len());
+
+    for (i, &v) in vertices.iter().enumerate() {
+        let c = cell(v, tolerance);
+        let mut found = None;
+
+        'search: for dx in -reach..reach + 1 {
+            for dy in -reach..reach + 1 {
+                for dz in -reach..reach + 1 {
+                    let neighbours = match grid.get(&[c[0] + dx, c[1] + dy, c[2] + dz]) {
+                        Some(n) => n,
+                        None => continue,
+                    };
+                    for &j in neighbours {
+                        let w = vertices[j as usize];
+                        let (a, b) = (uv(i), uv(j as usize));
+                        let (m, n) = (normal(i), normal(j as usize));
+                        let distance =
+                            ((v[0] - w[0]).powi(2) + (v[1] - w[1]).powi(2) + (v[2] - w[2]).powi(2))
+                                .sqrt();
+                        if distance <= tolerance
+                            && (a[0] - b[0]).abs() <= UV_TOLERANCE
+                            && (a[1] - b[1]).abs() <= UV_TOLERANCE
+                            && (0..3).all(|k| (m[k] - n[k]).abs() <= NORMAL_TOLERANCE)
+                        {
+                            found = Some(j);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+
+        match found {
+            Some(j) => remap.push(j),
+            None => {
+                grid.entry(c).or_default().push(i as u32);
+                remap.push(i as u32);
+            }
+        }
+    }
+
+    remap
+}
+
+// A triangle is degenerate when its height over the longest edge is within
+// the tolerance, which includes triangles using a vertex twice
+fn is_degenerate(vertices: &[[f32; 4]], t: &[u32; 4], tolerance: f32) -> bool {
+    if t[0] == t[1] || t[1] == t[2] || t[0] == t[2] {
+        return true;
+    }
+
+    let p = |i: u32| vertices[i as usize];
+    let (a, b, c) = (p(t[0]), p(t[1]), p(t[2]));
+    let e1 = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let e2 = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let e3 = [c[0] - b[0], c[1] - b[1], c[2] - b[2]];
+    let length = |e: [f32; 3]| (e[0] * e[0] + e[1] * e[1] + e[2] * e[2]).sqrt();
+    let double_area = length([
+        e1[1] * e2[2] - e1[2] * e2[1],
+        e1[2] * e2[0] - e1[0] * e2[2],
+        e1[0] * e2[1] - e1[1] * e2[0],
+    ]);
+    let longest = length(e1).max(length(e2)).max(length(e3));
+
+    double_area <= tolerance * longest
+}
+
+/**
+ * Removes the vertices no triangle uses, returns how many there were
+ */
+pub(crate) fn remove_unused_vertices(object: &mut Object) -> usize {
+    let mut used = vec![false; object.vertices.len()];
+    for t in &object.indices {
+        for &i in &t[..3] {
+            used[i as usize] = true;
+        }
+    }
+
+    let mut remap = vec![0; used.len()];
+    let mut kept = 0;
+    for i in 0..used.len() {
+        if used[i] {
+            object.vertices[kept] = object.vertices[i];
+            if i < object.uvs.len() {
+                object.uvs[kept] = object.uvs[i];
+            }
+            if i < object.normals.len() {
+                object.normals[kept] = object.normals[i];
+            }
+            remap[i] = kept as u32;
+            kept += 1;
+        }
+    }
+
+    let removed = object.vertices.len() - kept;
+    object.vertices.truncate(kept);
+    object.uvs.truncate(kept);
+    object.normals.truncate(kept);
+    for t in &mut object.indices {
+        for i in &mut t[..3] {
+            *i = remap[*i as usize];
+        }
+    }
+
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn welds_close_vertices_and_drops_what_collapses() {
+        // Two triangles sharing an edge, stored without sharing vertices, a
+        // sliver and a copy of the first triangle with the other winding
+        let mut mesh = Object::from_triangles(
+            vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [1.0, 0.0, 0.0005],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, -0.0005],
+                [0.5, 0.0, 0.0001],
+                [7.0, 7.0, 7.0],
+            ],
+            vec![[0, 1, 2], [3, 4, 5], [0, 6, 1], [0, 2, 1]],
+        );
+        let report = clean_up(&mut mesh, 0.001);
+
+        assert_eq!(
+            report,
+            Report {
+                welded_vertices: 2,
+                degenerate_triangles: 1,
+                duplicate_triangles: 1,
+                unused_vertices: 2,
+            }
+        );
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.uvs.len(), 4);
+        let indices: Vec<[u32; 4]> = vec![[0, 1, 2, 0], [1, 3, 2, 0]];
+        assert_eq!(mesh.indices, indices);
+    }
+
+    #[test]
+    fn keeps_texture_seams() {
+        let mut mesh = Object::from_triangles(
+            vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0],
+            ],
+            vec![[0, 1, 2], [3, 2, 1]],
+        );
+        mesh.uvs[3] = [0.5, 0.5];
+        let report = clean_up(&mut mesh, 0.001);
+
+        assert_eq!(report.welded_vertices, 0);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices.len(), 2);
+    }
+
+    #[test]
+    fn zero_tolerance_only_welds_identical_vertices() {
+        let mut mesh = Object::from_triangles(
+            vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.00001],
+                [1.0, 1.0, 0.0],
+            ],
+            vec![[0, 1, 2], [3, 5, 4]],
+        );
+        let report = clean_up(&mut mesh, 0.0);
+
+        assert_eq!(report.welded_vertices, 1);
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_eq!(mesh.indices[1][0], 1);
+    }
+}
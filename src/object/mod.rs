@@ -1,20 +1,592 @@
-mod bvh;
+pub mod bvh;
+pub mod cleanup;
+pub mod normals;
 mod object_loader;
+pub mod simplify;
+pub mod subdivide;
 
+use std::io;
+
+use self::subdivide::Scheme;
+use camera::Camera;
+
+/**
+ * The triangles of the scene. The fourth index of a triangle is the index of
+ * its material.
+ */
+#[derive(Clone)]
 pub struct Object {
     pub vertices: Vec<[f32; 4]>,
+    pub uvs: Vec<[f32; 2]>,
+    // Zero for vertices of flat shaded triangles
+    pub normals: Vec<[f32; 4]>,
     pub indices: Vec<[u32; 4]>,
+    // The polygon of the file every triangle was split from, the triangles of
+    // a polygon are next to each other. Empty when every triangle is a face
+    // of its own.
+    pub polygons: Vec<u32>,
     pub bvh: Vec<bvh::Node>,
 }
 
+/**
+ * Places a model in the scene. It is scaled first, then rotated around the
+ * x, y and z axes in that order and then moved.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: [f32; 3],
+    // Angles in radians
+    pub rotation: [f32; 3],
+    pub scale: f32,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            translation: [0.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0],
+            scale: 1.0,
+        }
+    }
+
+    pub fn apply(&self, p: [f32; 3]) -> [f32; 3] {
+        let [x, y, z] = self.rotate([p[0] * self.scale, p[1] * self.scale, p[2] * self.scale]);
+        [
+            x + self.translation[0],
+            y + self.translation[1],
+            z + self.translation[2],
+        ]
+    }
+
+    /**
+     * Turns a normal with the model. A negative scale mirrors it.
+     */
+    pub fn apply_normal(&self, n: [f32; 3]) -> [f32; 3] {
+        let sign = self.scale.signum();
+        self.rotate([n[0] * sign, n[1] * sign, n[2] * sign])
+    }
+
+    fn rotate(&self, [x, y, z]: [f32; 3]) -> [f32; 3] {
+        let (sin, cos) = self.rotation[0].sin_cos();
+        let [x, y, z] = [x, y * cos - z * sin, y * sin + z * cos];
+        let (sin, cos) = self.rotation[1].sin_cos();
+        let [x, y, z] = [x * cos + z * sin, y, z * cos - x * sin];
+        let (sin, cos) = self.rotation[2].sin_cos();
+        [x * cos - y * sin, x * sin + y * cos, z]
+    }
+}
+
+impl Object {
+    /**
+     * The smallest and the largest coordinates of the vertices
+     */
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        self.vertices.iter().fold(
+            ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+            |(min, max), v| {
+                (
+                    [min[0].min(v[0]), min[1].min(v[1]), min[2].min(v[2])],
+                    [max[0].max(v[0]), max[1].max(v[1]), max[2].max(v[2])],
+                )
+            },
+        )
+    }
+
+    pub fn translate(&mut self, offset: [f32; 3]) {
+        self.transform(&Transform {
+            translation: offset,
+            ..Transform::identity()
+        });
+    }
+
+    /**
+     * Rotates around the x, y and z axes in that order, the angles are in
+     * radians
+     */
+    pub fn rotate(&mut self, angles: [f32; 3]) {
+        self.transform(&Transform {
+            rotation: angles,
+            ..Transform::identity()
+        });
+    }
+
+    pub fn scale(&mut self, factor: f32) {
+        self.transform(&Transform {
+            scale: factor,
+            ..Transform::identity()
+        });
+    }
+
+    /**
+     * A negative scale mirrors the object, so the triangles are turned around
+     * to keep facing outwards
+     */
+    pub fn transform(&mut self, transform: &Transform) {
+        for v in &mut self.vertices {
+            let [x, y, z] = transform.apply([v[0], v[1], v[2]]);
+            *v = [x, y, z, v[3]];
+        }
+        for n in &mut self.normals {
+            let [x, y, z] = transform.apply_normal([n[0], n[1], n[2]]);
+            *n = [x, y, z, n[3]];
+        }
+        if transform.scale < 0.0 {
+            self.reverse_winding();
+        }
+    }
+
+    /**
+     * Moves the center of the bounding box to the origin
+     */
+    pub fn center(&mut self) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        let (min, max) = self.bounds();
+        self.translate([
+            -(min[0] + max[0]) / 2.0,
+            -(min[1] + max[1]) / 2.0,
+            -(min[2] + max[2]) / 2.0,
+        ]);
+    }
+
+    /**
+     * Centers the object and scales it so its longest side is 1
+     */
+    pub fn fit_to_unit_box(&mut self) {
+        self.center();
+        let (min, max) = self.bounds();
+        let size = (max[0] - min[0]).max(max[1] - min[1]).max(max[2] - min[2]);
+        if size > 0.0 {
+            self.scale(1.0 / size);
+        }
+    }
+
+    /**
+     * Mirrors the object along an axis, 0 is x, 1 is y and 2 is z. This
+     * switches between left and right handed coordinates.
+     */
+    pub fn flip(&mut self, axis: usize) {
+        for v in self.vertices.iter_mut().chain(&mut self.normals) {
+            v[axis] = -v[axis];
+        }
+        self.reverse_winding();
+    }
+
+    /**
+     * Turns an object modelled with z pointing up so that y points up, like
+     * in the scene
+     */
+    pub fn z_up_to_y_up(&mut self) {
+        for v in self.vertices.iter_mut().chain(&mut self.normals) {
+            *v = [v[0], v[2], -v[1], v[3]];
+        }
+    }
+
+    /**
+     * Builds the BVH again, which has to happen after the vertices were moved
+     */
+    pub fn build_bvh(&mut self) {
+        self.bvh = bvh::construct_bvh(&self.vertices, &self.indices);
+    }
+
+    /**
+     * Writes the object to an ASCII PLY file
+     */
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        object_loader::save_model(
+            path,
+            &self.vertices,
+            &self.uvs,
+            &self.normals,
+            &self.indices,
+        )
+    }
+
+    /**
+     * Keeps the triangles, and their polygons, that are marked in `keep`
+     */
+    pub(crate) fn retain_triangles(&mut self, keep: &[bool]) {
+        if self.polygons.len() == self.indices.len() {
+            let mut kept = keep.iter();
+            self.polygons.retain(|_| *kept.next().unwrap());
+        }
+        let mut kept = keep.iter();
+        self.indices.retain(|_| *kept.next().unwrap());
+    }
+
+    /**
+     * The triangles with material 0, without texture coordinates, normals or
+     * polygons, for the tests of the mesh passes
+     */
+    #[cfg(test)]
+    pub(crate) fn from_triangles(vertices: Vec<[f32; 3]>, indices: Vec<[u32; 3]>) -> Object {
+        Object {
+            uvs: vec![[0.0, 0.0]; vertices.len()],
+            normals: vec![[0.0; 4]; vertices.len()],
+            vertices: vertices
+                .into_iter()
+                .map(|[x, y, z]| [x, y, z, 0.0])
+                .collect(),
+            indices: indices.into_iter().map(|[i, j, k]| [i, j, k, 0]).collect(),
+            polygons: vec![],
+            bvh: vec![],
+        }
+    }
+
+    // Mirroring changes the order the corners of a triangle are seen in
+    fn reverse_winding(&mut self) {
+        for t in &mut self.indices {
+            t.swap(1, 2);
+        }
+    }
+}
+
+/**
+ * How many of the triangles of a model are kept
+ */
+#[derive(Clone, Copy, Debug)]
+pub enum Detail {
+    Full,
+    // Decimated to this many triangles, or until the next collapse would move
+    // the surface by more than max_error
+    Decimated {
+        triangles: usize,
+        max_error: f32,
+    },
+    // The level, out of the model and this many levels with half the
+    // triangles each, that has one triangle per pixels_per_triangle pixels
+    // of the model as seen by the camera in an image width pixels wide
+    Auto {
+        levels: usize,
+        camera: Camera,
+        width: u32,
+        pixels_per_triangle: f32,
+    },
+}
+
+/**
+ * What Model::load() did to a model, for the caller to report
+ */
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoadReport {
+    // What the cleanup removed, if the model was cleaned up
+    pub clean_up: Option<cleanup::Report>,
+    // How far the model was simplified, unless it has its full detail
+    pub detail: Option<DetailReport>,
+}
+
+/**
+ * The triangles left of a model that was simplified
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DetailReport {
+    // With the largest distance a collapse moved the surface by
+    Decimated {
+        triangles: usize,
+        error: f32,
+    },
+    // The level picked for the width the model covers in the image
+    Auto {
+        level: usize,
+        triangles: usize,
+        projected_width: f32,
+    },
+}
+
+/**
+ * A model file, how it is placed and the material its triangles use. The
+ * model is cleaned up, subdivided, flipped, turned to y up, centered or
+ * fitted, transformed, decimated and given smooth normals in that order.
+ */
+#[derive(Clone, Debug)]
+pub struct Model {
+    pub path: String,
+    // Tolerance of cleanup::clean_up(), in the units of the file
+    pub clean_up: Option<f32>,
+    // Computes smooth normals with this crease angle in radians, instead of
+    // using the normals of the file or shading the triangles flat
+    pub crease_angle: Option<f32>,
+    // Crease angle of the smooth normals computed for a file without normals,
+    // which is shaded flat otherwise
+    pub fallback_crease_angle: Option<f32>,
+    // Levels of Object::subdivide()
+    pub subdivision: Option<(usize, Scheme)>,
+    // Mirrors the model along the x, y and z axes
+    pub flip: [bool; 3],
+    pub z_up: bool,
+    pub center: bool,
+    // Centers the model and scales it to a longest side of 1
+    pub fit: bool,
+    pub transform: Transform,
+    pub detail: Detail,
+    pub material: u32,
+}
+
+impl Model {
+    /**
+     * The model as it is in the file, with the first material
+     */
+    pub fn new(path: &str) -> Model {
+        Model {
+            path: path.to_string(),
+            clean_up: None,
+            crease_angle: None,
+            fallback_crease_angle: None,
+            subdivision: None,
+            flip: [false; 3],
+            z_up: false,
+            center: false,
+            fit: false,
+            transform: Transform::identity(),
+            detail: Detail::Full,
+            material: 0,
+        }
+    }
+
+    /**
+     * Loads the model and puts it in place, along with what was changed about
+     * it on the way. It has no BVH yet.
+     */
+    pub fn load(&self) -> (Object, LoadReport) {
+        let mut report = LoadReport::default();
+        let (vertices, uvs, normals, indices, polygons) = object_loader::load_model(&self.path);
+        let mut object = Object {
+            vertices,
+            uvs,
+            normals,
+            indices: indices
+                .into_iter()
+                .map(|[i, j, k, _]| [i, j, k, self.material])
+                .collect(),
+            polygons,
+            bvh: vec![],
+        };
+        let has_normals = object.normals.iter().any(|n| n[..3] != [0.0, 0.0, 0.0]);
+
+        if let Some(tolerance) = self.clean_up {
+            report.clean_up = Some(cleanup::clean_up(&mut object, tolerance));
+        }
+        if let Some((levels, scheme)) = self.subdivision {
+            object.subdivide(levels, scheme);
+        }
+        for axis in 0..3 {
+            if self.flip[axis] {
+                object.flip(axis);
+            }
+        }
+        if self.z_up {
+            object.z_up_to_y_up();
+        }
+        if self.fit {
+            object.fit_to_unit_box();
+        } else if self.center {
+            object.center();
+        }
+        object.transform(&self.transform);
+
+        match self.detail {
+            Detail::Full => {}
+            Detail::Decimated {
+                triangles,
+                max_error,
+            } => {
+                let error = object.decimate(triangles, max_error);
+                report.detail = Some(DetailReport::Decimated {
+                    triangles: object.indices.len(),
+                    error,
+                });
+            }
+            Detail::Auto {
+                levels,
+                camera,
+                width,
+                pixels_per_triangle,
+            } => {
+                let (min, max) = object.bounds();
+                let center = [
+                    (min[0] + max[0]) / 2.0,
+                    (min[1] + max[1]) / 2.0,
+                    (min[2] + max[2]) / 2.0,
+                ];
+                let radius = ((max[0] - min[0]).powi(2)
+                    + (max[1] - min[1]).powi(2)
+                    + (max[2] - min[2]).powi(2))
+                .sqrt()
+                    / 2.0;
+                let size = camera.projected_size(center, radius, width);
+
+                let mut lods = object.levels_of_detail(levels);
+                let level = simplify::select_lod(&lods, size, pixels_per_triangle);
+                object = lods.swap_remove(level);
+                report.detail = Some(DetailReport::Auto {
+                    level,
+                    triangles: object.indices.len(),
+                    projected_width: size,
+                });
+            }
+        }
+
+        let crease_angle = match self.crease_angle {
+            None if !has_normals => self.fallback_crease_angle,
+            angle => angle,
+        };
+        if let Some(angle) = crease_angle {
+            normals::smooth_normals(&mut object, angle);
+        }
+
+        (object, report)
+    }
+}
+
 pub fn load_object(path: &str) -> Object {
-    let (vertices, indices) = object_loader::load_model(path);
+    load_models(&[Model::new(path)])
+}
 
-    let bvh = bvh::construct_bvh(&vertices, &indices);
+/**
+ * Loads several models into one object, see merge()
+ */
+pub fn load_models(models: &[Model]) -> Object {
+    merge(models.iter().map(|model| model.load().0).collect())
+}
+
+/**
+ * Joins objects into one with a BVH over all of them, so they share the
+ * vertex, index and BVH buffers of the shaders. The polygons are not kept.
+ */
+pub fn merge(objects: Vec<Object>) -> Object {
+    let mut vertices = vec![];
+    let mut uvs = vec![];
+    let mut normals = vec![];
+    let mut indices = vec![];
+
+    for object in objects {
+        let offset = vertices.len() as u32;
+
+        vertices.extend(object.vertices);
+        uvs.extend(object.uvs);
+        normals.extend(object.normals);
+        indices.extend(
+            object
+                .indices
+                .into_iter()
+                .map(|[i, j, k, material]| [i + offset, j + offset, k + offset, material]),
+        );
+    }
 
-    Object {
+    let mut object = Object {
         vertices,
+        uvs,
+        normals,
         indices,
-        bvh,
+        polygons: vec![],
+        bvh: vec![],
+    };
+    object.build_bvh();
+    object
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+        (0..3).all(|i| (a[i] - b[i]).abs() < 1e-5)
+    }
+
+    fn vertex(object: &Object, i: usize) -> [f32; 3] {
+        let [x, y, z, _] = object.vertices[i];
+        [x, y, z]
+    }
+
+    fn normal(object: &Object) -> [f32; 3] {
+        let [i, j, k, _] = object.indices[0];
+        let (a, b, c) = (
+            vertex(object, i as usize),
+            vertex(object, j as usize),
+            vertex(object, k as usize),
+        );
+        let (e1, e2) = (
+            [b[0] - a[0], b[1] - a[1], b[2] - a[2]],
+            [c[0] - a[0], c[1] - a[1], c[2] - a[2]],
+        );
+        [
+            e1[1] * e2[2] - e1[2] * e2[1],
+            e1[2] * e2[0] - e1[0] * e2[2],
+            e1[0] * e2[1] - e1[1] * e2[0],
+        ]
+    }
+
+    // A triangle facing +z
+    fn triangle() -> Object {
+        let mut triangle = Object::from_triangles(
+            vec![[1.0, 2.0, 3.0], [3.0, 2.0, 3.0], [1.0, 6.0, 3.0]],
+            vec![[0, 1, 2]],
+        );
+        triangle.normals = vec![[0.0, 0.0, 1.0, 0.0]; 3];
+        triangle
+    }
+
+    #[test]
+    fn transform_scales_rotates_and_moves() {
+        let transform = Transform {
+            translation: [1.0, 0.0, 0.0],
+            rotation: [0.0, ::std::f32::consts::FRAC_PI_2, 0.0],
+            scale: 2.0,
+        };
+        // (1, 0, 0) becomes (2, 0, 0), turns to (0, 0, -2) and moves
+        assert!(close(transform.apply([1.0, 0.0, 0.0]), [1.0, 0.0, -2.0]));
+    }
+
+    #[test]
+    fn fit_to_unit_box_centers_the_longest_side() {
+        let mut object = triangle();
+        object.fit_to_unit_box();
+        let (min, max) = object.bounds();
+        assert!(close(min, [-0.25, -0.5, 0.0]));
+        assert!(close(max, [0.25, 0.5, 0.0]));
+    }
+
+    #[test]
+    fn mirroring_keeps_triangles_facing_outwards() {
+        let mut object = triangle();
+        object.flip(2);
+        assert!(normal(&object)[2] < 0.0);
+        assert_eq!(object.normals[0], [0.0, 0.0, -1.0, 0.0]);
+
+        let mut object = triangle();
+        object.scale(-1.0);
+        assert!(normal(&object)[2] < 0.0);
+        assert!(close(vertex(&object, 0), [-1.0, -2.0, -3.0]));
+        assert_eq!(object.normals[0], [0.0, 0.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn z_up_to_y_up_is_a_rotation() {
+        let mut object = triangle();
+        object.z_up_to_y_up();
+        assert!(close(vertex(&object, 0), [1.0, 3.0, -2.0]));
+        let n = normal(&object);
+        assert!(n[0].abs() < 1e-5 && n[1] > 0.0 && n[2].abs() < 1e-5);
+        assert_eq!(object.normals[0], [0.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn normals_are_only_computed_for_files_without_them() {
+        let path = ::std::env::temp_dir().join("fallback_normals.ply");
+        let path = path.to_str().unwrap();
+        let model = Model {
+            fallback_crease_angle: Some(1.0),
+            ..Model::new(path)
+        };
+
+        let mut object = triangle();
+        object.normals = vec![[0.6, 0.0, 0.8, 0.0]; 3];
+        object.save(path).unwrap();
+        assert_eq!(model.load().0.normals, object.normals);
+
+        object.normals = vec![[0.0; 4]; 3];
+        object.save(path).unwrap();
+        assert_eq!(model.load().0.normals, vec![[0.0, 0.0, 1.0, 0.0]; 3]);
     }
 }
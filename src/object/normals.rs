@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use super::Object;
+
+fn sub(a: [f32; 4], b: [f32; 4]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    if length > 0.0 {
+        [v[0] / length, v[1] / length, v[2] / length]
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
+
+// Angle between the two edges leaving a corner
+fn angle(corner: [f32; 4], a: [f32; 4], b: [f32; 4]) -> f32 {
+    let cos = dot(normalize(sub(a, corner)), normalize(sub(b, corner)));
+    cos.max(-1.0).min(1.0).acos()
+}
+
+/**
+ * Gives every vertex the average of the normals of the triangles around it,
+ * weighted by the angle of the triangles at the vertex. Triangles meeting at
+ * more than the crease angle (in radians) are not smoothed with each other,
+ * the vertex is split into one per side of the hard edge. The BVH has to be
+ * built again afterwards.
+ */
+pub fn smooth_normals(object: &mut Object, crease_angle: f32) {
+    let vertices = &object.vertices;
+    let corner = |t: &[u32; 4], c: usize| vertices[t[c] as usize];
+
+    let face_normals: Vec<[f32; 3]> = object
+        .indices
+        .iter()
+        .map(|t| {
+            normalize(cross(
+                sub(corner(t, 1), corner(t, 0)),
+                sub(corner(t, 2), corner(t, 0)),
+            ))
+        })
+        .collect();
+    let weights: Vec<[f32; 3]> = object
+        .indices
+        .iter()
+        .map(|t| {
+            let mut w = [0.0; 3];
+            for (c, weight) in w.iter_mut().enumerate() {
+                *weight = angle(corner(t, c), corner(t, (c + 1) % 3), corner(t, (c + 2) % 3));
+            }
+            w
+        })
+        .collect();
+
+    // The triangle and corner of every use of a vertex
+    let mut corners = vec![vec![]; vertices.len()];
+    for (t, indices) in object.indices.iter().enumerate() {
+        for c in 0..3 {
+            corners[indices[c] as usize].push((t, c));
+        }
+    }
+
+    let min_cos = crease_angle.cos();
+    let mut new_vertices = vec![];
+    let mut new_uvs = vec![];
+    let mut normals = vec![];
+    let mut new_indices = object.indices.clone();
+    // Corners with the same smoothed normal share a vertex again
+    let mut split: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+
+    for (t, indices) in object.indices.iter().enumerate() {
+        for c in 0..3 {
+            let v = indices[c];
+            let mut sum = [0.0; 3];
+            for &(other, other_c) in &corners[v as usize] {
+                let n = face_normals[other];
+                if dot(face_normals[t], n) >= min_cos {
+                    let w = weights[other][other_c];
+                    sum = [sum[0] + n[0] * w, sum[1] + n[1] * w, sum[2] + n[2] * w];
+                }
+            }
+            let n = match normalize(sum) {
+                [0.0, 0.0, 0.0] => face_normals[t],
+                n => n,
+            };
+
+            let key = (v, [n[0].to_bits(), n[1].to_bits(), n[2].to_bits()]);
+            new_indices[t][c] = *split.entry(key).or_insert_with(|| {
+                new_vertices.push(vertices[v as usize]);
+                new_uvs.push(object.uvs.get(v as usize).cloned().unwrap_or([0.0, 0.0]));
+                normals.push([n[0], n[1], n[2], 0.0]);
+                new_vertices.len() as u32 - 1
+            });
+        }
+    }
+
+    object.vertices = new_vertices;
+    object.uvs = new_uvs;
+    object.normals = normals;
+    object.indices = new_indices;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A cube of 12 triangles over 8 shared corners, facing outwards
+    fn cube() -> Object {
+        let vertices = (0..8)
+            .map(|i| {
+                [
+                    (i & 1) as f32 * 2.0 - 1.0,
+                    (i >> 1 & 1) as f32 * 2.0 - 1.0,
+                    (i >> 2 & 1) as f32 * 2.0 - 1.0,
+                ]
+            })
+            .collect();
+        let quads = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let mut indices = vec![];
+        for q in &quads {
+            indices.push([q[0], q[1], q[2]]);
+            indices.push([q[0], q[2], q[3]]);
+        }
+        Object::from_triangles(vertices, indices)
+    }
+
+    #[test]
+    fn smooth_corners_point_away_from_the_center() {
+        let mut object = cube();
+        smooth_normals(&mut object, 100f32.to_radians());
+
+        assert_eq!(object.vertices.len(), 8);
+        for (v, n) in object.vertices.iter().zip(&object.normals) {
+            let expected = normalize([v[0], v[1], v[2]]);
+            assert!(
+                dot([n[0], n[1], n[2]], expected) > 0.9999,
+                "{:?} at {:?}",
+                n,
+                v
+            );
+        }
+    }
+
+    #[test]
+    fn hard_edges_split_the_corners() {
+        let mut object = cube();
+        smooth_normals(&mut object, 30f32.to_radians());
+
+        // Every corner once per side
+        assert_eq!(object.vertices.len(), 24);
+        for (t, indices) in object.indices.iter().enumerate() {
+            let n = object.normals[indices[0] as usize];
+            for &i in &indices[..3] {
+                assert_eq!(object.normals[i as usize], n, "triangle {}", t);
+            }
+            let p = object.vertices[indices[0] as usize];
+            assert_eq!(dot([n[0], n[1], n[2]], [p[0], p[1], p[2]]), 1.0);
+        }
+    }
+}
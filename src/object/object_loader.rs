@@ -1,5 +1,5 @@
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::ops::Deref;
 use std::str::FromStr;
 
@@ -10,16 +10,43 @@ enum ParseState {
     Indices,
 }
 
-pub fn load_model(path: &str) -> (Vec<[f32; 4]>, Vec<[u32; 4]>) {
+// Property names used by different exporters for texture coordinates
+const U_NAMES: [&str; 3] = ["u", "s", "texture_u"];
+const V_NAMES: [&str; 3] = ["v", "t", "texture_v"];
+
+fn find_property(properties: &[String], names: &[&str]) -> Option<usize> {
+    properties.iter().position(|p| names.contains(&p.deref()))
+}
+
+// Vertices, texture coordinates, normals and triangles of a model, and the
+// face of the file every triangle belongs to
+type Mesh = (
+    Vec<[f32; 4]>,
+    Vec<[f32; 2]>,
+    Vec<[f32; 4]>,
+    Vec<[u32; 4]>,
+    Vec<u32>,
+);
+
+/**
+ * Loads an ASCII PLY file. Vertices without texture coordinates get (0, 0)
+ * and vertices without normals get a zero normal.
+ */
+pub fn load_model(path: &str) -> Mesh {
     let file = File::open(path).unwrap();
 
     let line_iter = BufReader::new(file).lines();
 
     let mut state = ParseState::Header;
 
-    let mut vecs: (Vec<[f32; 4]>, Vec<[u32; 4]>) = (vec![], vec![]);
+    let mut vecs: Mesh = (vec![], vec![], vec![], vec![], vec![]);
+    let mut face = 0;
     let mut i: usize = 0;
     let mut vert_count = 0;
+    let mut vertex_properties: Vec<String> = vec![];
+    let mut position = [0, 1, 2];
+    let mut uv = None;
+    let mut normal = None;
 
     for line in line_iter {
         let l = line.unwrap();
@@ -32,39 +59,123 @@ pub fn load_model(path: &str) -> (Vec<[f32; 4]>, Vec<[u32; 4]>) {
                     if i == 0 {
                         vert_count = x;
                         vecs.0.reserve_exact(x);
-                    } else {
                         vecs.1.reserve_exact(x);
+                        vecs.2.reserve_exact(x);
+                    } else {
+                        vecs.3.reserve_exact(x);
                     }
                     i = i + 1;
                 }
+                if s.starts_with("property") && i == 1 {
+                    let name = s.split_whitespace().last().unwrap();
+                    vertex_properties.push(name.to_string());
+                }
                 if s == "end_header" {
+                    position = [
+                        find_property(&vertex_properties, &["x"]).unwrap(),
+                        find_property(&vertex_properties, &["y"]).unwrap(),
+                        find_property(&vertex_properties, &["z"]).unwrap(),
+                    ];
+                    uv = find_property(&vertex_properties, &U_NAMES)
+                        .and_then(|u| find_property(&vertex_properties, &V_NAMES).map(|v| (u, v)));
+                    normal = match (
+                        find_property(&vertex_properties, &["nx"]),
+                        find_property(&vertex_properties, &["ny"]),
+                        find_property(&vertex_properties, &["nz"]),
+                    ) {
+                        (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+                        _ => None,
+                    };
                     state = ParseState::Vertices;
                 }
             }
             ParseState::Vertices => {
                 vert_count = vert_count - 1;
-                let mut numbers = s.split_whitespace();
+                let numbers: Vec<f32> = s
+                    .split_whitespace()
+                    .map(|n| f32::from_str(n).unwrap())
+                    .collect();
                 vecs.0.push([
-                    f32::from_str(numbers.next().unwrap()).unwrap(),
-                    f32::from_str(numbers.next().unwrap()).unwrap(),
-                    f32::from_str(numbers.next().unwrap()).unwrap(),
+                    numbers[position[0]],
+                    numbers[position[1]],
+                    numbers[position[2]],
                     0.0,
                 ]);
+                // Images are stored top down, texture coordinates start at the bottom
+                vecs.1.push(match uv {
+                    Some((u, v)) => [numbers[u], 1.0 - numbers[v]],
+                    None => [0.0, 0.0],
+                });
+                vecs.2.push(match normal {
+                    Some([x, y, z]) => [numbers[x], numbers[y], numbers[z], 0.0],
+                    None => [0.0, 0.0, 0.0, 0.0],
+                });
                 if vert_count == 0 {
                     state = ParseState::Indices;
                 }
             }
             ParseState::Indices => {
-                let mut numbers = s.split_whitespace().skip(1);
-                vecs.1.push([
-                    u32::from_str_radix(numbers.next().unwrap(), 10).unwrap(),
-                    u32::from_str_radix(numbers.next().unwrap(), 10).unwrap(),
-                    u32::from_str_radix(numbers.next().unwrap(), 10).unwrap(),
-                    0,
-                ]);
+                // Polygons are split into a fan of triangles around the first
+                // corner, which remember the polygon they belong to
+                let corners: Vec<u32> = s
+                    .split_whitespace()
+                    .skip(1)
+                    .map(|n| u32::from_str_radix(n, 10).unwrap())
+                    .collect();
+                for i in 2..corners.len() {
+                    vecs.3.push([corners[0], corners[i - 1], corners[i], 0]);
+                    vecs.4.push(face);
+                }
+                face += 1;
             }
         }
     }
 
     vecs
 }
+
+/**
+ * Writes an ASCII PLY file that load_model() reads back the same. Normals
+ * are only written if there are any.
+ */
+pub fn save_model(
+    path: &str,
+    vertices: &[[f32; 4]],
+    uvs: &[[f32; 2]],
+    normals: &[[f32; 4]],
+    indices: &[[u32; 4]],
+) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    let has_normals = normals.iter().any(|n| n[..3] != [0.0, 0.0, 0.0]);
+
+    writeln!(
+        file,
+        "ply\nformat ascii 1.0\nelement vertex {}",
+        vertices.len()
+    )?;
+    writeln!(file, "property float x\nproperty float y\nproperty float z")?;
+    writeln!(file, "property float u\nproperty float v")?;
+    if has_normals {
+        writeln!(
+            file,
+            "property float nx\nproperty float ny\nproperty float nz"
+        )?;
+    }
+    writeln!(file, "element face {}", indices.len())?;
+    writeln!(file, "property list uchar int vertex_indices\nend_header")?;
+
+    for (i, v) in vertices.iter().enumerate() {
+        let uv = uvs.get(i).cloned().unwrap_or([0.0, 0.0]);
+        write!(file, "{} {} {} {} {}", v[0], v[1], v[2], uv[0], 1.0 - uv[1])?;
+        if has_normals {
+            let n = normals[i];
+            write!(file, " {} {} {}", n[0], n[1], n[2])?;
+        }
+        writeln!(file)?;
+    }
+    for t in indices {
+        writeln!(file, "3 {} {} {}", t[0], t[1], t[2])?;
+    }
+
+    file.flush()
+}
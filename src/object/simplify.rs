@@ -0,0 +1,627 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use super::{cleanup, Object};
+
+// Borders of the mesh are held in place by planes through them, weighted this
+// much more than the triangles
+const BOUNDARY_WEIGHT: f64 = 1000.0;
+
+type Vec3 = [f64; 3];
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: Vec3) -> Vec3 {
+    let length = dot(v, v).sqrt();
+    if length > 0.0 {
+        [v[0] / length, v[1] / length, v[2] / length]
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/**
+ * Sum of the squared distances to a set of planes, the upper half of the
+ * symmetric 4x4 matrix
+ */
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane(n: Vec3, p: Vec3, weight: f64) -> Quadric {
+        let [a, b, c] = n;
+        let d = -dot(n, p);
+        let q = [
+            a * a,
+            a * b,
+            a * c,
+            a * d,
+            b * b,
+            b * c,
+            b * d,
+            c * c,
+            c * d,
+            d * d,
+        ];
+        Quadric([
+            q[0] * weight,
+            q[1] * weight,
+            q[2] * weight,
+            q[3] * weight,
+            q[4] * weight,
+            q[5] * weight,
+            q[6] * weight,
+            q[7] * weight,
+            q[8] * weight,
+            q[9] * weight,
+        ])
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut q = self.0;
+        for (a, b) in q.iter_mut().zip(&other.0) {
+            *a += b;
+        }
+        Quadric(q)
+    }
+
+    fn error(&self, [x, y, z]: Vec3) -> f64 {
+        let q = &self.0;
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+
+    // The point of least error, if there is a single one
+    fn minimum(&self) -> Option<Vec3> {
+        let q = &self.0;
+        let (a, b, c, d, e, f) = (q[0], q[1], q[2], q[4], q[5], q[7]);
+        let cofactors = [
+            [d * f - e * e, c * e - b * f, b * e - c * d],
+            [c * e - b * f, a * f - c * c, b * c - a * e],
+            [b * e - c * d, b * c - a * e, a * d - b * b],
+        ];
+        let det = a * cofactors[0][0] + b * cofactors[0][1] + c * cofactors[0][2];
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        let r = [-q[3], -q[6], -q[8]];
+        Some([
+            dot(cofactors[0], r) / det,
+            dot(cofactors[1], r) / det,
+            dot(cofactors[2], r) / det,
+        ])
+    }
+}
+
+// An edge that can be collapsed, as long as its vertices did not change since
+struct Collapse {
+    error: f64,
+    a: u32,
+    b: u32,
+    position: Vec3,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Collapse) -> bool {
+        self.error == other.error
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Collapse) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// The cheapest collapse comes first out of the max-heap
+impl Ord for Collapse {
+    fn cmp(&self, other: &Collapse) -> Ordering {
+        other
+            .error
+            .partial_cmp(&self.error)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+struct Simplifier<'a> {
+    object: &'a mut Object,
+    positions: Vec<Vec3>,
+    quadrics: Vec<Quadric>,
+    triangles: Vec<[u32; 4]>,
+    alive: Vec<bool>,
+    // Triangles around every vertex, including removed ones
+    around: Vec<Vec<usize>>,
+    removed: Vec<bool>,
+    versions: Vec<u32>,
+    heap: BinaryHeap<Collapse>,
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(object: &'a mut Object) -> Simplifier<'a> {
+        let positions: Vec<Vec3> = object
+            .vertices
+            .iter()
+            .map(|v| [f64::from(v[0]), f64::from(v[1]), f64::from(v[2])])
+            .collect();
+        let triangles = object.indices.clone();
+        let count = positions.len();
+
+        let mut quadrics = vec![Quadric::default(); count];
+        let mut around = vec![vec![]; count];
+        let mut edges: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+        for (t, indices) in triangles.iter().enumerate() {
+            let [a, b, c] = [
+                positions[indices[0] as usize],
+                positions[indices[1] as usize],
+                positions[indices[2] as usize],
+            ];
+            let q = Quadric::plane(normalize(cross(sub(b, a), sub(c, a))), a, 1.0);
+            for k in 0..3 {
+                let i = indices[k] as usize;
+                quadrics[i] = quadrics[i].add(&q);
+                around[i].push(t);
+
+                let j = indices[(k + 1) % 3];
+                edges
+                    .entry((indices[k].min(j), indices[k].max(j)))
+                    .or_default()
+                    .push(t);
+            }
+        }
+
+        // A plane standing on every border edge keeps the border in place
+        for (&(i, j), users) in &edges {
+            if users.len() != 1 {
+                continue;
+            }
+            let t = triangles[users[0]];
+            let [a, b, c] = [
+                positions[t[0] as usize],
+                positions[t[1] as usize],
+                positions[t[2] as usize],
+            ];
+            let normal = cross(sub(b, a), sub(c, a));
+            let (p, q) = (positions[i as usize], positions[j as usize]);
+            let border = normalize(cross(sub(q, p), normal));
+            let plane = Quadric::plane(border, p, BOUNDARY_WEIGHT);
+            quadrics[i as usize] = quadrics[i as usize].add(&plane);
+            quadrics[j as usize] = quadrics[j as usize].add(&plane);
+        }
+
+        let mut simplifier = Simplifier {
+            alive: vec![true; triangles.len()],
+            removed: vec![false; count],
+            versions: vec![0; count],
+            heap: BinaryHeap::new(),
+            object,
+            positions,
+            quadrics,
+            triangles,
+            around,
+        };
+        // In a fixed order, so ties are broken the same way every time
+        let mut edges: Vec<(u32, u32)> = edges.keys().cloned().collect();
+        edges.sort();
+        for (i, j) in edges {
+            simplifier.push(i, j);
+        }
+        simplifier
+    }
+
+    // Queues the collapse of an edge into the point of least error
+    fn push(&mut self, a: u32, b: u32) {
+        let q = self.quadrics[a as usize].add(&self.quadrics[b as usize]);
+        let (pa, pb) = (self.positions[a as usize], self.positions[b as usize]);
+        let middle = [
+            (pa[0] + pb[0]) / 2.0,
+            (pa[1] + pb[1]) / 2.0,
+            (pa[2] + pb[2]) / 2.0,
+        ];
+
+        let mut best = (q.error(middle), middle);
+        for &p in q.minimum().iter().chain(&[pa, pb]) {
+            let error = q.error(p);
+            if error < best.0 {
+                best = (error, p);
+            }
+        }
+
+        self.heap.push(Collapse {
+            error: best.0.max(0.0),
+            a,
+            b,
+            position: best.1,
+            versions: (self.versions[a as usize], self.versions[b as usize]),
+        });
+    }
+
+    fn normal(&self, t: &[u32; 4], moved: &[u32], position: Vec3) -> Vec3 {
+        let p = |i: u32| {
+            if moved.contains(&i) {
+                position
+            } else {
+                self.positions[i as usize]
+            }
+        };
+        let (a, b, c) = (p(t[0]), p(t[1]), p(t[2]));
+        normalize(cross(sub(b, a), sub(c, a)))
+    }
+
+    fn neighbours(&self, v: u32) -> Vec<u32> {
+        let mut neighbours: Vec<u32> = self.around[v as usize]
+            .iter()
+            .filter(|&&t| self.alive[t])
+            .flat_map(|&t| self.triangles[t][..3].to_vec())
+            .filter(|&i| i != v)
+            .collect();
+        neighbours.sort();
+        neighbours.dedup();
+        neighbours
+    }
+
+    // Joining vertices that share more neighbours than the corners opposite
+    // of their edge would pinch the surface into a non-manifold one
+    fn pinches(&self, a: u32, b: u32) -> bool {
+        let of_b = self.neighbours(b);
+        let shared = self
+            .neighbours(a)
+            .iter()
+            .filter(|v| of_b.binary_search(v).is_ok())
+            .count();
+        let opposite = self.around[a as usize]
+            .iter()
+            .filter(|&&t| self.alive[t] && self.triangles[t][..3].contains(&b))
+            .count();
+        shared > opposite
+    }
+
+    // Whether moving both vertices to the position turns a triangle over
+    fn flips(&self, a: u32, b: u32, position: Vec3) -> bool {
+        self.around[a as usize]
+            .iter()
+            .chain(&self.around[b as usize])
+            .filter(|&&t| self.alive[t])
+            .map(|&t| self.triangles[t])
+            .filter(|t| !(t[..3].contains(&a) && t[..3].contains(&b)))
+            .any(|t| {
+                dot(
+                    self.normal(&t, &[], position),
+                    self.normal(&t, &[a, b], position),
+                ) <= 0.0
+            })
+    }
+
+    // Moves a to the position and replaces b by it, returns the number of
+    // triangles that collapsed
+    fn collapse(&mut self, a: u32, b: u32, position: Vec3) -> usize {
+        let (ai, bi) = (a as usize, b as usize);
+
+        // The attributes follow the position along the edge
+        let edge = sub(self.positions[bi], self.positions[ai]);
+        let length = dot(edge, edge);
+        let s = if length > 0.0 {
+            (dot(sub(position, self.positions[ai]), edge) / length)
+                .max(0.0)
+                .min(1.0) as f32
+        } else {
+            0.0
+        };
+        if let (Some(&ua), Some(&ub)) = (self.object.uvs.get(ai), self.object.uvs.get(bi)) {
+            self.object.uvs[ai] = [lerp(ua[0], ub[0], s), lerp(ua[1], ub[1], s)];
+        }
+        if let (Some(&na), Some(&nb)) = (self.object.normals.get(ai), self.object.normals.get(bi)) {
+            let n = normalize([
+                f64::from(lerp(na[0], nb[0], s)),
+                f64::from(lerp(na[1], nb[1], s)),
+                f64::from(lerp(na[2], nb[2], s)),
+            ]);
+            self.object.normals[ai] = [n[0] as f32, n[1] as f32, n[2] as f32, 0.0];
+        }
+
+        self.positions[ai] = position;
+        self.quadrics[ai] = self.quadrics[ai].add(&self.quadrics[bi]);
+        self.removed[bi] = true;
+        self.versions[ai] += 1;
+
+        let mut collapsed = 0;
+        let around_b = ::std::mem::take(&mut self.around[bi]);
+        for t in around_b {
+            if !self.alive[t] {
+                continue;
+            }
+            if self.triangles[t][..3].contains(&a) {
+                self.alive[t] = false;
+                collapsed += 1;
+            } else {
+                for i in &mut self.triangles[t][..3] {
+                    if *i == b {
+                        *i = a;
+                    }
+                }
+                self.around[ai].push(t);
+            }
+        }
+        let alive = &self.alive;
+        self.around[ai].retain(|&t| alive[t]);
+
+        for n in self.neighbours(a) {
+            self.push(a, n);
+        }
+
+        collapsed
+    }
+
+    fn run(&mut self, target: usize, max_error: f32) -> f32 {
+        let max_error = f64::from(max_error) * f64::from(max_error);
+        let mut count = self.triangles.len();
+        let mut worst = 0.0;
+
+        while count > target {
+            let c = match self.heap.pop() {
+                Some(c) => c,
+                None => break,
+            };
+            let (a, b) = (c.a as usize, c.b as usize);
+            if self.removed[a]
+                || self.removed[b]
+                || (self.versions[a], self.versions[b]) != c.versions
+            {
+                continue;
+            }
+            if c.error > max_error {
+                break;
+            }
+            if self.pinches(c.a, c.b) || self.flips(c.a, c.b, c.position) {
+                continue;
+            }
+
+            count -= self.collapse(c.a, c.b, c.position);
+            worst = c.error.max(worst);
+        }
+
+        worst.sqrt() as f32
+    }
+
+    fn finish(self) {
+        let Simplifier {
+            object,
+            positions,
+            triangles,
+            alive,
+            ..
+        } = self;
+
+        for (v, p) in object.vertices.iter_mut().zip(&positions) {
+            *v = [p[0] as f32, p[1] as f32, p[2] as f32, v[3]];
+        }
+        object.indices = triangles
+            .into_iter()
+            .zip(alive)
+            .filter(|&(_, alive)| alive)
+            .map(|(t, _)| t)
+            .collect();
+        // Collapses join triangles of different polygons
+        object.polygons.clear();
+        cleanup::remove_unused_vertices(object);
+    }
+}
+
+impl Object {
+    /**
+     * Collapses edges in the order of the least quadric error until at most
+     * `triangles` are left, or until the next collapse would move the surface
+     * by roughly more than `max_error`. The mesh should be welded with
+     * cleanup::clean_up() first, only shared vertices join triangles. Returns
+     * the largest error of a collapse. The BVH has to be built again
+     * afterwards.
+     */
+    pub fn decimate(&mut self, triangles: usize, max_error: f32) -> f32 {
+        let mut simplifier = Simplifier::new(self);
+        let error = simplifier.run(triangles, max_error);
+        simplifier.finish();
+        error
+    }
+
+    /**
+     * The object followed by the given number of levels, each with half the
+     * triangles of the one before
+     */
+    pub fn levels_of_detail(&self, levels: usize) -> Vec<Object> {
+        let mut lods = vec![self.clone()];
+        for _ in 0..levels {
+            let mut lod = lods.last().unwrap().clone();
+            let triangles = lod.indices.len() / 2;
+            lod.decimate(triangles, f32::INFINITY);
+            lods.push(lod);
+        }
+        lods
+    }
+}
+
+/**
+ * The coarsest of the levels of detail that still has a triangle for every
+ * `pixels_per_triangle` pixels of the area it covers in the image, given the
+ * projected width of the object in pixels
+ */
+pub fn select_lod(lods: &[Object], projected_width: f32, pixels_per_triangle: f32) -> usize {
+    let area = ::std::f32::consts::PI / 4.0 * projected_width * projected_width;
+    let needed = (area / pixels_per_triangle) as usize;
+    (0..lods.len())
+        .rev()
+        .find(|&i| lods[i].indices.len() >= needed)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A closed sphere made from a subdivided octahedron
+    fn sphere(subdivisions: usize) -> Object {
+        let mut vertices = vec![
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+        ];
+        let mut indices: Vec<[u32; 3]> = vec![
+            [0, 2, 4],
+            [2, 1, 4],
+            [1, 3, 4],
+            [3, 0, 4],
+            [2, 0, 5],
+            [1, 2, 5],
+            [3, 1, 5],
+            [0, 3, 5],
+        ];
+        for _ in 0..subdivisions {
+            let mut middles: HashMap<(u32, u32), u32> = HashMap::new();
+            let mut middle = |a: u32, b: u32, vertices: &mut Vec<Vec3>| {
+                *middles.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    let (p, q) = (vertices[a as usize], vertices[b as usize]);
+                    vertices.push(normalize([p[0] + q[0], p[1] + q[1], p[2] + q[2]]));
+                    vertices.len() as u32 - 1
+                })
+            };
+            indices = indices
+                .iter()
+                .flat_map(|&[a, b, c]| {
+                    let ab = middle(a, b, &mut vertices);
+                    let bc = middle(b, c, &mut vertices);
+                    let ca = middle(c, a, &mut vertices);
+                    vec![[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]
+                })
+                .collect();
+        }
+
+        Object::from_triangles(
+            vertices
+                .into_iter()
+                .map(|[x, y, z]| [x as f32, y as f32, z as f32])
+                .collect(),
+            indices,
+        )
+    }
+
+    // Every edge is shared by two triangles
+    fn is_closed(object: &Object) -> bool {
+        let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+        for t in &object.indices {
+            for k in 0..3 {
+                let (a, b) = (t[k], t[(k + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        edges.values().all(|&n| n == 2)
+    }
+
+    #[test]
+    fn decimates_to_the_target() {
+        let mut object = sphere(4);
+        assert_eq!(object.indices.len(), 2048);
+
+        let error = object.decimate(200, f32::INFINITY);
+        assert!(object.indices.len() <= 200 && object.indices.len() > 150);
+        assert!(is_closed(&object));
+        assert_eq!(object.uvs.len(), object.vertices.len());
+        assert!(error > 0.0 && error < 0.2, "error {}", error);
+
+        // Still a sphere
+        for v in &object.vertices {
+            let r = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+            assert!(r > 0.85 && r < 1.05, "radius {}", r);
+        }
+        for t in &object.indices {
+            let p = |i: u32| {
+                let v = object.vertices[i as usize];
+                [f64::from(v[0]), f64::from(v[1]), f64::from(v[2])]
+            };
+            let n = cross(sub(p(t[1]), p(t[0])), sub(p(t[2]), p(t[0])));
+            assert!(dot(n, p(t[0])) > 0.0, "triangle turned inwards");
+        }
+    }
+
+    #[test]
+    fn stops_at_the_error_bound() {
+        let mut object = sphere(4);
+        let error = object.decimate(0, 0.01);
+        assert!(error <= 0.01);
+        assert!(object.indices.len() < 2048);
+        assert!(object.indices.len() > 200);
+    }
+
+    #[test]
+    fn flat_regions_collapse_without_error() {
+        // A grid of 8x8 squares in a plane keeps its border
+        let n = 9;
+        let vertices = (0..n * n)
+            .map(|i| [(i % n) as f32, (i / n) as f32, 0.0])
+            .collect();
+        let mut indices = vec![];
+        for y in 0..n - 1 {
+            for x in 0..n - 1 {
+                let i = y * n + x;
+                indices.push([i, i + 1, i + n + 1]);
+                indices.push([i, i + n + 1, i + n]);
+            }
+        }
+        let mut object = Object::from_triangles(vertices, indices);
+
+        let error = object.decimate(0, 0.0001);
+        assert!(error < 0.0001);
+        assert!(
+            object.indices.len() < 10,
+            "{} triangles",
+            object.indices.len()
+        );
+        let (min, max) = object.bounds();
+        assert_eq!((min, max), ([0.0, 0.0, 0.0], [8.0, 8.0, 0.0]));
+    }
+
+    #[test]
+    fn levels_halve_the_triangles() {
+        let lods = sphere(3).levels_of_detail(3);
+        let counts: Vec<usize> = lods.iter().map(|l| l.indices.len()).collect();
+        assert_eq!(counts[0], 512);
+        for i in 1..4 {
+            assert!(counts[i] <= counts[i - 1] / 2 && counts[i] > 0);
+        }
+
+        assert_eq!(select_lod(&lods, 1000.0, 1.0), 0);
+        assert_eq!(select_lod(&lods, 1.0, 1.0), 3);
+        let picked = select_lod(&lods, 30.0, 4.0);
+        assert!(lods[picked].indices.len() as f32 >= 0.785 * 900.0 / 4.0 - 1.0);
+    }
+}
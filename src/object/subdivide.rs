@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+
+use super::Object;
+
+// Position, texture coordinates and normal of a vertex, which are all
+// smoothed with the same weights
+type Attributes = [f32; 8];
+
+/**
+ * How the faces of a mesh are refined
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    // Every triangle into four triangles
+    Loop,
+    // Every face into one quad per corner, stored as two triangles each. The
+    // faces are the polygons of the file the loader split into triangles.
+    CatmullClark,
+}
+
+impl Scheme {
+    pub fn from_name(name: &str) -> Option<Scheme> {
+        match name {
+            "loop" => Some(Scheme::Loop),
+            "catmull-clark" => Some(Scheme::CatmullClark),
+            _ => None,
+        }
+    }
+}
+
+fn attributes(object: &Object, i: usize) -> Attributes {
+    let v = object.vertices[i];
+    let uv = object.uvs.get(i).cloned().unwrap_or([0.0, 0.0]);
+    let n = object.normals.get(i).cloned().unwrap_or([0.0; 4]);
+    [v[0], v[1], v[2], uv[0], uv[1], n[0], n[1], n[2]]
+}
+
+// Sum of the attributes of some vertices, each with its own weight
+fn weighted<I: IntoIterator<Item = (Attributes, f32)>>(terms: I) -> Attributes {
+    let mut sum = [0.0; 8];
+    for (a, weight) in terms {
+        for k in 0..8 {
+            sum[k] += a[k] * weight;
+        }
+    }
+    sum
+}
+
+fn average(points: &[Attributes]) -> Attributes {
+    let weight = 1.0 / points.len() as f32;
+    weighted(points.iter().map(|&p| (p, weight)))
+}
+
+// The corners of a polygon in order, found by following the edges of its
+// triangles that no other of them shares. None if they are not one loop.
+fn outline(triangles: &[[u32; 4]]) -> Option<Vec<u32>> {
+    let edges: Vec<(u32, u32)> = triangles
+        .iter()
+        .flat_map(|t| vec![(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+        .collect();
+    let border: Vec<(u32, u32)> = edges
+        .iter()
+        .filter(|&&(a, b)| !edges.contains(&(b, a)))
+        .cloned()
+        .collect();
+    let next: HashMap<u32, u32> = border.iter().cloned().collect();
+    if border.is_empty() || next.len() != border.len() {
+        return None;
+    }
+
+    let first = border[0].0;
+    let mut corners = vec![first];
+    let mut c = next[&first];
+    while c != first {
+        if corners.len() == border.len() {
+            return None;
+        }
+        corners.push(c);
+        c = *next.get(&c)?;
+    }
+    if corners.len() == border.len() {
+        Some(corners)
+    } else {
+        None
+    }
+}
+
+// The faces of the mesh. With `polygons` the triangles split from the same
+// polygon of the file are joined into it again, otherwise and when the
+// polygons are not known every triangle is a face.
+fn faces(object: &Object, polygons: bool) -> Vec<(Vec<u32>, u32)> {
+    let indices = &object.indices;
+    let triangle = |t: &[u32; 4]| (vec![t[0], t[1], t[2]], t[3]);
+    if !polygons || object.polygons.len() != indices.len() {
+        return indices.iter().map(triangle).collect();
+    }
+
+    let mut faces = vec![];
+    let mut start = 0;
+    while start < indices.len() {
+        let mut end = start + 1;
+        while end < indices.len() && object.polygons[end] == object.polygons[start] {
+            end += 1;
+        }
+        let group = &indices[start..end];
+        match outline(group) {
+            Some(corners) => faces.push((corners, group[0][3])),
+            None => faces.extend(group.iter().map(triangle)),
+        }
+        start = end;
+    }
+    faces
+}
+
+/**
+ * The edges of a mesh and the faces next to them. Edges with other than two
+ * faces are creases, they are smoothed only along the crease so the border of
+ * an open mesh stays where it is.
+ */
+struct Edges {
+    index: HashMap<(u32, u32), usize>,
+    ends: Vec<(u32, u32)>,
+    faces: Vec<Vec<usize>>,
+    // Neighbours of every vertex, and those along creases
+    neighbours: Vec<Vec<u32>>,
+    creases: Vec<Vec<u32>>,
+}
+
+impl Edges {
+    fn new(vertices: usize, faces: &[(Vec<u32>, u32)]) -> Edges {
+        let mut edges = Edges {
+            index: HashMap::new(),
+            ends: vec![],
+            faces: vec![],
+            neighbours: vec![vec![]; vertices],
+            creases: vec![vec![]; vertices],
+        };
+        for (f, (face, _)) in faces.iter().enumerate() {
+            for c in 0..face.len() {
+                let (a, b) = (face[c], face[(c + 1) % face.len()]);
+                let key = (a.min(b), a.max(b));
+                let e = match edges.index.get(&key) {
+                    Some(&e) => e,
+                    None => {
+                        edges.ends.push(key);
+                        edges.faces.push(vec![]);
+                        edges.neighbours[a as usize].push(b);
+                        edges.neighbours[b as usize].push(a);
+                        edges.index.insert(key, edges.ends.len() - 1);
+                        edges.ends.len() - 1
+                    }
+                };
+                edges.faces[e].push(f);
+            }
+        }
+        for e in 0..edges.ends.len() {
+            if edges.faces[e].len() != 2 {
+                let (a, b) = edges.ends[e];
+                edges.creases[a as usize].push(b);
+                edges.creases[b as usize].push(a);
+            }
+        }
+        edges
+    }
+
+    fn get(&self, a: u32, b: u32) -> usize {
+        self.index[&(a.min(b), a.max(b))]
+    }
+
+    fn is_crease(&self, e: usize) -> bool {
+        self.faces[e].len() != 2
+    }
+}
+
+// The new position of an old vertex. Smooth vertices follow the rule of the
+// scheme, vertices on a crease are smoothed along it and the others stay.
+fn vertex_point<F: Fn(usize) -> Attributes>(
+    v: usize,
+    points: &[Attributes],
+    edges: &Edges,
+    smooth: F,
+) -> Attributes {
+    let creases = &edges.creases[v];
+    match creases.len() {
+        _ if edges.neighbours[v].is_empty() => points[v],
+        0 => smooth(v),
+        2 => weighted(vec![
+            (points[v], 0.75),
+            (points[creases[0] as usize], 0.125),
+            (points[creases[1] as usize], 0.125),
+        ]),
+        _ => points[v],
+    }
+}
+
+fn loop_step(object: &Object) -> Object {
+    let points: Vec<Attributes> = (0..object.vertices.len())
+        .map(|i| attributes(object, i))
+        .collect();
+    let faces = faces(object, false);
+    let edges = Edges::new(points.len(), &faces);
+    let mut new_points = vec![];
+
+    for v in 0..points.len() {
+        new_points.push(vertex_point(v, &points, &edges, |v| {
+            let n = edges.neighbours[v].len();
+            let beta = if n == 3 {
+                3.0 / 16.0
+            } else {
+                3.0 / (8.0 * n as f32)
+            };
+            weighted(
+                Some((points[v], 1.0 - n as f32 * beta)).into_iter().chain(
+                    edges.neighbours[v]
+                        .iter()
+                        .map(|&w| (points[w as usize], beta)),
+                ),
+            )
+        }));
+    }
+
+    // The corner of each face next to the edge that is not on the edge
+    let opposite = |f: usize, a: u32, b: u32| {
+        *faces[f].0.iter().find(|&&c| c != a && c != b).unwrap_or(&a) as usize
+    };
+    for e in 0..edges.ends.len() {
+        let (a, b) = edges.ends[e];
+        let (pa, pb) = (points[a as usize], points[b as usize]);
+        new_points.push(if edges.is_crease(e) {
+            average(&[pa, pb])
+        } else {
+            let c = opposite(edges.faces[e][0], a, b);
+            let d = opposite(edges.faces[e][1], a, b);
+            weighted(vec![
+                (pa, 0.375),
+                (pb, 0.375),
+                (points[c], 0.125),
+                (points[d], 0.125),
+            ])
+        });
+    }
+
+    let edge_point = |a: u32, b: u32| (points.len() + edges.get(a, b)) as u32;
+    let mut indices = vec![];
+    for &(ref face, material) in &faces {
+        let (a, b, c) = (face[0], face[1], face[2]);
+        let (ab, bc, ca) = (edge_point(a, b), edge_point(b, c), edge_point(c, a));
+        indices.push([a, ab, ca, material]);
+        indices.push([ab, b, bc, material]);
+        indices.push([ca, bc, c, material]);
+        indices.push([ab, bc, ca, material]);
+    }
+
+    object_from(&new_points, indices, vec![])
+}
+
+fn catmull_clark_step(object: &Object) -> Object {
+    let points: Vec<Attributes> = (0..object.vertices.len())
+        .map(|i| attributes(object, i))
+        .collect();
+    let faces = faces(object, true);
+    let edges = Edges::new(points.len(), &faces);
+
+    let face_points: Vec<Attributes> = faces
+        .iter()
+        .map(|(face, _)| {
+            let corners: Vec<Attributes> = face.iter().map(|&c| points[c as usize]).collect();
+            average(&corners)
+        }).collect();
+    let edge_points: Vec<Attributes> = (0..edges.ends.len())
+        .map(|e| {
+            let (a, b) = edges.ends[e];
+            let mut around = vec![points[a as usize], points[b as usize]];
+            if !edges.is_crease(e) {
+                around.extend(edges.faces[e].iter().map(|&f| face_points[f]));
+            }
+            average(&around)
+        }).collect();
+
+    let mut vertex_faces = vec![vec![]; points.len()];
+    for (f, (face, _)) in faces.iter().enumerate() {
+        for &c in face {
+            vertex_faces[c as usize].push(f);
+        }
+    }
+
+    let mut new_points = vec![];
+    for v in 0..points.len() {
+        new_points.push(vertex_point(v, &points, &edges, |v| {
+            // (F + 2R + (n - 3)P) / n with the average F of the face points
+            // and R of the edge middles around the vertex
+            let n = edges.neighbours[v].len() as f32;
+            let f: Vec<Attributes> = vertex_faces[v].iter().map(|&f| face_points[f]).collect();
+            let r: Vec<Attributes> = edges.neighbours[v]
+                .iter()
+                .map(|&w| average(&[points[v], points[w as usize]]))
+                .collect();
+            weighted(vec![
+                (average(&f), 1.0 / n),
+                (average(&r), 2.0 / n),
+                (points[v], (n - 3.0) / n),
+            ])
+        }));
+    }
+    new_points.extend(edge_points);
+    new_points.extend(face_points);
+
+    let edge_point = |a: u32, b: u32| (points.len() + edges.get(a, b)) as u32;
+    let face_point = |f: usize| (points.len() + edges.ends.len() + f) as u32;
+    let mut indices = vec![];
+    for (f, &(ref face, material)) in faces.iter().enumerate() {
+        let n = face.len();
+        for c in 0..n {
+            let (previous, v, next) = (face[(c + n - 1) % n], face[c], face[(c + 1) % n]);
+            let (after, before) = (edge_point(v, next), edge_point(previous, v));
+            indices.push([v, after, face_point(f), material]);
+            indices.push([v, face_point(f), before, material]);
+        }
+    }
+    // Both triangles of a quad belong to it
+    let polygons = (0..indices.len() as u32).map(|i| i / 2).collect();
+
+    object_from(&new_points, indices, polygons)
+}
+
+fn object_from(points: &[Attributes], indices: Vec<[u32; 4]>, polygons: Vec<u32>) -> Object {
+    Object {
+        vertices: points.iter().map(|p| [p[0], p[1], p[2], 0.0]).collect(),
+        uvs: points.iter().map(|p| [p[3], p[4]]).collect(),
+        normals: points
+            .iter()
+            .map(|p| {
+                let length = (p[5] * p[5] + p[6] * p[6] + p[7] * p[7]).sqrt();
+                if length > 0.0 {
+                    [p[5] / length, p[6] / length, p[7] / length, 0.0]
+                } else {
+                    [0.0; 4]
+                }
+            }).collect(),
+        indices,
+        polygons,
+        bvh: vec![],
+    }
+}
+
+impl Object {
+    /**
+     * Refines the mesh the given number of times. Only shared vertices join
+     * faces, so it should be welded with cleanup::clean_up() first. Texture
+     * seams and borders are creases, vertices where more than two creases
+     * meet stay in place. The BVH has to be built again afterwards.
+     */
+    pub fn subdivide(&mut self, levels: usize, scheme: Scheme) {
+        for _ in 0..levels {
+            *self = match scheme {
+                Scheme::Loop => loop_step(self),
+                Scheme::CatmullClark => catmull_clark_step(self),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A cube from -1 to 1 with its quads split and recorded like the loader
+    // does
+    fn cube() -> Object {
+        let vertices = (0..8)
+            .map(|i| {
+                [
+                    (i & 1) as f32 * 2.0 - 1.0,
+                    (i >> 1 & 1) as f32 * 2.0 - 1.0,
+                    (i >> 2 & 1) as f32 * 2.0 - 1.0,
+                ]
+            })
+            .collect();
+        let quads = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let mut indices = vec![];
+        for q in &quads {
+            indices.push([q[0], q[1], q[2]]);
+            indices.push([q[0], q[2], q[3]]);
+        }
+        let mut cube = Object::from_triangles(vertices, indices);
+        cube.polygons = (0..12).map(|i| i / 2).collect();
+        cube
+    }
+
+    fn close(a: [f32; 4], b: [f32; 3]) -> bool {
+        (0..3).all(|k| (a[k] - b[k]).abs() < 1e-6)
+    }
+
+    #[test]
+    fn loop_shrinks_a_closed_mesh_smoothly() {
+        let mut mesh = cube();
+        mesh.subdivide(2, Scheme::Loop);
+
+        assert_eq!(mesh.indices.len(), 12 * 16);
+        // Nothing leaves the cube and the corners are rounded off
+        for v in &mesh.vertices {
+            assert!(v[..3].iter().all(|c| c.abs() <= 1.0), "{:?}", v);
+        }
+        for v in &mesh.vertices[..8] {
+            assert!(v[..3].iter().all(|c| c.abs() < 0.9), "{:?}", v);
+        }
+    }
+
+    #[test]
+    fn loop_keeps_open_borders_on_the_border() {
+        let mut square = Object::from_triangles(
+            vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+        );
+        square.subdivide(1, Scheme::Loop);
+
+        assert_eq!(square.indices.len(), 8);
+        assert!(close(square.vertices[0], [0.125, 0.125, 0.0]));
+        // The middle of the bottom edge only depends on the edge
+        assert!(square.vertices.iter().any(|&v| close(v, [0.5, 0.0, 0.0])));
+        assert!(square.vertices.iter().all(|v| v[2] == 0.0));
+    }
+
+    #[test]
+    fn catmull_clark_refines_the_quads() {
+        let mut mesh = cube();
+        mesh.subdivide(1, Scheme::CatmullClark);
+
+        // 8 corners, 12 edges and 6 faces become 24 quads
+        assert_eq!(mesh.vertices.len(), 26);
+        assert_eq!(mesh.indices.len(), 48);
+        let c = 5.0 / 9.0;
+        assert!(close(mesh.vertices[7], [c, c, c]));
+        assert!(mesh.vertices.iter().any(|&v| close(v, [0.75, 0.75, 0.0])));
+        assert!(mesh.vertices.iter().any(|&v| close(v, [1.0, 0.0, 0.0])));
+
+        // The quads are found again on the next level
+        mesh.subdivide(1, Scheme::CatmullClark);
+        assert_eq!(mesh.indices.len(), 48 * 4);
+    }
+
+    #[test]
+    fn catmull_clark_joins_only_triangles_of_the_same_polygon() {
+        // Without the polygons of the file every triangle is a face
+        let mut mesh = cube();
+        mesh.polygons = vec![];
+        mesh.subdivide(1, Scheme::CatmullClark);
+
+        assert_eq!(mesh.vertices.len(), 8 + 18 + 12);
+        assert_eq!(mesh.indices.len(), 12 * 3 * 2);
+
+        let mut pentagon = Object::from_triangles(
+            vec![
+                [1.0, 0.0, 0.0],
+                [0.3, 0.95, 0.0],
+                [-0.8, 0.6, 0.0],
+                [-0.8, -0.6, 0.0],
+                [0.3, -0.95, 0.0],
+            ],
+            vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]],
+        );
+        pentagon.polygons = vec![0, 0, 0];
+        pentagon.subdivide(1, Scheme::CatmullClark);
+
+        // One quad per corner around a single face point
+        assert_eq!(pentagon.vertices.len(), 5 + 5 + 1);
+        assert_eq!(pentagon.indices.len(), 5 * 2);
+        assert!(close(pentagon.vertices[10], [0.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn catmull_clark_finds_quads_after_the_winding_is_reversed() {
+        let mut mesh = cube();
+        for t in &mut mesh.indices {
+            t.swap(1, 2);
+        }
+        mesh.subdivide(1, Scheme::CatmullClark);
+
+        assert_eq!(mesh.vertices.len(), 26);
+        let c = 5.0 / 9.0;
+        assert!(close(mesh.vertices[7], [c, c, c]));
+    }
+}
@@ -0,0 +1,430 @@
+use std::env;
+use std::process;
+use std::str::FromStr;
+use std::thread;
+
+use coding_assignment::clock::Step;
+use coding_assignment::object::subdivide::Scheme;
+use coding_assignment::object::{Detail, Model};
+use coding_assignment::post::Effect;
+use coding_assignment::renderer::{WorkgroupSize, DEFAULT_MAX_DEPTH};
+use coding_assignment::tonemap::{Operator, ToneMapping};
+
+const DEFAULT_MODEL: &str = "resources/bunny_low_res.ply";
+
+const DEFAULT_CREASE_ANGLE: f32 = 60.0;
+
+const DEFAULT_PIXELS_PER_TRIANGLE: f32 = 4.0;
+
+const USAGE: &str = "Usage: coding_assignment [options]
+
+Options:
+  --model <file.ply>         Model to render, can be given several times
+                             (default: resources/bunny_low_res.ply)
+  The following options apply to the last model given:
+  --clean-up <tolerance>     Weld vertices closer than the tolerance and remove
+                             degenerate, duplicate and unused geometry
+  --subdivide <levels>[:<scheme>]
+                             Smooth the model by subdividing it this many
+                             times with the loop or catmull-clark scheme, the
+                             latter keeps the quads of the file (default: loop)
+  --crease-angle <degrees>   Smooth the normals of the model, except across
+                             edges sharper than this, even if it has normals.
+                             Models without normals are smoothed with an
+                             angle of 60 unless this is given.
+  --file-normals             Only use the normals stored in the model, shade
+                             the triangles flat if it has none
+  --flip <axes>              Mirror the model along some of the axes x, y and
+                             z, for example xz, to switch the handedness
+  --z-up                     The model is made with z up, turn it to y up
+  --center                   Move the center of the model to the origin
+  --fit                      Center the model and scale it to a longest side
+                             of 1, before the scale below
+  --decimate <triangles>     Simplify the model down to this many triangles
+  --max-error <distance>     Stop simplifying before the surface moves by more
+                             than this, after the transforms
+  --auto-lod <levels>[:<pixels>]
+                             Make this many levels of detail with half the
+                             triangles each and use the one with a triangle
+                             per this many pixels (default: 4) as seen from
+                             the camera at --time in the window, or in the
+                             image of --reference
+  --write-lods <levels>      Write this many levels of detail of the model to
+                             <model>_lod<n>.ply files, level 0 being the
+                             model itself
+  --position <x>,<y>,<z>     Where the model is moved to (default: 0,0,0)
+  --rotation <x>,<y>,<z>     Rotation in degrees around the x, y and z axes
+                             in that order (default: 0,0,0)
+  --scale <factor>           Size of the model (default: 1)
+  --color <r>,<g>,<b>        Color of the model (default: 0,0,0.5)
+  --reflectivity <r>         Fraction of light the model reflects like a
+                             mirror (default: 0.2)
+  --transparency <t>         Fraction of light refracted through the model
+                             (default: 0)
+  --ior <n>                  Index of refraction of the model (default: 1.5)
+  --albedo <image>           Color texture of the model
+  --normal-map <image>       Tangent space normal map of the model
+  --roughness-map <image>    Roughness texture of the model, read from green
+
+  --env <file.hdr>           Equirectangular Radiance HDR environment map
+  --env-rotation <degrees>   Initial rotation of the environment map
+  --tonemap <operator>       reinhard, aces or uncharted2 (default: aces)
+  --exposure <stops>         Exposure adjustment before tone mapping (default: 0)
+  --gamma <gamma>            Display gamma (default: 2.2)
+  --post <effects>           Comma separated post-processing effects, applied
+                             in order, or none (default: bloom,fxaa,vignette)
+                               bloom[:threshold[:intensity[:levels]]]
+                               vignette[:strength]
+                               chromatic[:pixels]
+                               fxaa
+                               grade:<lut.png>[:strength]
+  --max-depth <n>            Maximum number of reflections and refractions
+                             along a ray (default: 4)
+  --fragment                 Trace in the fragment shader instead of the
+                             compute shader, C switches at runtime
+  --workgroup <W>x<H>        Workgroup size of the compute shader (default: 8x8)
+  --tile-size <pixels>       Trace the image in square tiles over several
+                             submissions, 0 traces it at once (default: 0)
+  --tiles-per-submit <n>     Number of tiles traced per submission (default: 1)
+  --time <seconds>           Scene time to start at (default: 0)
+  --speed <factor>           How fast scene time passes (default: 1)
+  --fixed-step <seconds>     Advance the scene by a fixed step per frame
+                             instead of following the wall clock
+  --paused                   Start with the scene time paused
+  --capture <frames>         Write this many frames as PNGs and exit
+  --capture-fps <fps>        Simulated frame rate of a capture, which always
+                             uses a fixed step (default: 30)
+  --capture-dir <directory>  Where captured frames go (default: capture)
+  --reference <file.png>     Render one frame on the CPU without Vulkan, write
+                             it to the file and exit. Only the mesh is traced
+                             and there is no post-processing.
+  --size <W>x<H>             Size of the reference image (default: 1024x768)
+  --threads <n>              Threads of the reference renderer (default: one
+                             per CPU core)
+  -h, --help                 Print this help";
+
+/**
+ * A model given on the command line, every model gets its own material
+ */
+pub struct ModelOptions {
+    pub model: Model,
+    pub color: [f32; 3],
+    pub reflectivity: f32,
+    pub transparency: f32,
+    pub ior: f32,
+    pub albedo: Option<String>,
+    pub normal_map: Option<String>,
+    pub roughness_map: Option<String>,
+    // Levels and pixels per triangle of Detail::Auto, which needs the camera
+    pub auto_lod: Option<(usize, f32)>,
+    pub write_lods: Option<usize>,
+}
+
+impl ModelOptions {
+    fn new(path: &str) -> ModelOptions {
+        ModelOptions {
+            model: Model {
+                fallback_crease_angle: Some(DEFAULT_CREASE_ANGLE.to_radians()),
+                ..Model::new(path)
+            },
+            color: [0.0, 0.0, 0.5],
+            reflectivity: 0.2,
+            transparency: 0.0,
+            ior: 1.5,
+            albedo: None,
+            normal_map: None,
+            roughness_map: None,
+            auto_lod: None,
+            write_lods: None,
+        }
+    }
+}
+
+/**
+ * Settings given on the command line
+ */
+pub struct Options {
+    pub models: Vec<ModelOptions>,
+    pub environment: Option<String>,
+    pub environment_rotation: f32,
+    pub tone_mapping: ToneMapping,
+    pub post: Vec<Effect>,
+    pub max_depth: u32,
+    pub compute: bool,
+    pub workgroup_size: WorkgroupSize,
+    pub tile_size: u32,
+    pub tiles_per_submit: usize,
+    pub start_time: f64,
+    pub speed: f64,
+    pub step: Step,
+    pub paused: bool,
+    pub capture: Option<usize>,
+    pub capture_fps: f32,
+    pub capture_dir: String,
+    pub reference: Option<String>,
+    pub reference_size: [u32; 2],
+    pub threads: usize,
+}
+
+fn value<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> String {
+    match args.next() {
+        Some(v) => v,
+        None => fail(&format!("missing value for {}", flag)),
+    }
+}
+
+fn number<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> f32 {
+    let v = value(args, flag);
+    match v.parse() {
+        Ok(n) => n,
+        Err(_) => fail(&format!("invalid number for {}: {}", flag, v)),
+    }
+}
+
+// A whole number that fits the type, negative or fractional values are errors
+fn integer<T: FromStr, I: Iterator<Item = String>>(args: &mut I, flag: &str) -> T {
+    let v = value(args, flag);
+    match v.parse() {
+        Ok(n) => n,
+        Err(_) => fail(&format!("invalid whole number for {}: {}", flag, v)),
+    }
+}
+
+fn size<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> [u32; 2] {
+    let v = value(args, flag);
+    let sizes: Vec<Option<u32>> = v.split('x').map(|n| n.parse().ok()).collect();
+    match sizes[..] {
+        [Some(width), Some(height)] if width > 0 && height > 0 => [width, height],
+        _ => fail(&format!("invalid size for {}: {}", flag, v)),
+    }
+}
+
+fn vector<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> [f32; 3] {
+    let v = value(args, flag);
+    let numbers: Vec<Option<f32>> = v.split(',').map(|n| n.parse().ok()).collect();
+    match numbers[..] {
+        [Some(x), Some(y), Some(z)] => [x, y, z],
+        _ => fail(&format!("invalid vector for {}: {}", flag, v)),
+    }
+}
+
+// The model the per-model options apply to, the default one if none was given
+fn last_model(models: &mut Vec<ModelOptions>) -> &mut ModelOptions {
+    if models.is_empty() {
+        models.push(ModelOptions::new(DEFAULT_MODEL));
+    }
+    models.last_mut().unwrap()
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(1);
+}
+
+impl Options {
+    pub fn from_args() -> Options {
+        let mut options = Options {
+            models: vec![],
+            environment: None,
+            environment_rotation: 0.0,
+            tone_mapping: ToneMapping {
+                operator: Operator::Aces,
+                exposure: 0.0,
+                gamma: 2.2,
+            },
+            post: Effect::defaults(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            compute: true,
+            workgroup_size: WorkgroupSize {
+                width: 8,
+                height: 8,
+            },
+            tile_size: 0,
+            tiles_per_submit: 1,
+            start_time: 0.0,
+            speed: 1.0,
+            step: Step::RealTime,
+            paused: false,
+            capture: None,
+            capture_fps: 30.0,
+            capture_dir: "capture".to_string(),
+            reference: None,
+            reference_size: [1024, 768],
+            threads: thread::available_parallelism().map_or(4, |n| n.get()),
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_ref() {
+                "--model" => options
+                    .models
+                    .push(ModelOptions::new(&value(&mut args, &arg))),
+                "--flip" => {
+                    let axes = value(&mut args, &arg);
+                    if axes.is_empty() || !axes.chars().all(|c| "xyz".contains(c)) {
+                        fail(&format!("invalid axes for {}: {}", arg, axes));
+                    }
+                    let model = &mut last_model(&mut options.models).model;
+                    for (axis, name) in "xyz".chars().enumerate() {
+                        model.flip[axis] = axes.contains(name);
+                    }
+                }
+                "--clean-up" => {
+                    last_model(&mut options.models).model.clean_up = Some(number(&mut args, &arg))
+                }
+                "--subdivide" => {
+                    let v = value(&mut args, &arg);
+                    let mut parts = v.splitn(2, ':');
+                    let levels = parts.next().and_then(|n| n.parse().ok());
+                    let scheme = match parts.next() {
+                        Some(name) => Scheme::from_name(name),
+                        None => Some(Scheme::Loop),
+                    };
+                    match (levels, scheme) {
+                        (Some(levels), Some(scheme)) => {
+                            last_model(&mut options.models).model.subdivision =
+                                Some((levels, scheme))
+                        }
+                        _ => fail(&format!("invalid subdivision for {}: {}", arg, v)),
+                    }
+                }
+                "--crease-angle" => {
+                    last_model(&mut options.models).model.crease_angle =
+                        Some(number(&mut args, &arg).to_radians())
+                }
+                "--file-normals" => {
+                    let model = &mut last_model(&mut options.models).model;
+                    model.crease_angle = None;
+                    model.fallback_crease_angle = None;
+                }
+                "--z-up" => last_model(&mut options.models).model.z_up = true,
+                "--center" => last_model(&mut options.models).model.center = true,
+                "--fit" => last_model(&mut options.models).model.fit = true,
+                "--decimate" => {
+                    let triangles = integer(&mut args, &arg);
+                    let model = &mut last_model(&mut options.models).model;
+                    model.detail = match model.detail {
+                        Detail::Decimated { max_error, .. } => Detail::Decimated {
+                            triangles,
+                            max_error,
+                        },
+                        _ => Detail::Decimated {
+                            triangles,
+                            max_error: f32::INFINITY,
+                        },
+                    };
+                }
+                "--max-error" => {
+                    let max_error = number(&mut args, &arg);
+                    let model = &mut last_model(&mut options.models).model;
+                    model.detail = match model.detail {
+                        Detail::Decimated { triangles, .. } => Detail::Decimated {
+                            triangles,
+                            max_error,
+                        },
+                        _ => Detail::Decimated {
+                            triangles: 0,
+                            max_error,
+                        },
+                    };
+                }
+                "--auto-lod" => {
+                    let v = value(&mut args, &arg);
+                    let mut parts = v.splitn(2, ':');
+                    let levels = parts.next().and_then(|n| n.parse().ok());
+                    let pixels = match parts.next() {
+                        Some(p) => p.parse().ok(),
+                        None => Some(DEFAULT_PIXELS_PER_TRIANGLE),
+                    };
+                    match (levels, pixels) {
+                        (Some(levels), Some(pixels)) if pixels > 0.0 => {
+                            last_model(&mut options.models).auto_lod = Some((levels, pixels))
+                        }
+                        _ => fail(&format!("invalid levels of detail for {}: {}", arg, v)),
+                    }
+                }
+                "--write-lods" => {
+                    last_model(&mut options.models).write_lods = Some(integer(&mut args, &arg))
+                }
+                "--position" => {
+                    last_model(&mut options.models).model.transform.translation =
+                        vector(&mut args, &arg)
+                }
+                "--rotation" => {
+                    let [x, y, z] = vector(&mut args, &arg);
+                    last_model(&mut options.models).model.transform.rotation =
+                        [x.to_radians(), y.to_radians(), z.to_radians()];
+                }
+                "--scale" => {
+                    last_model(&mut options.models).model.transform.scale = number(&mut args, &arg)
+                }
+                "--color" => last_model(&mut options.models).color = vector(&mut args, &arg),
+                "--reflectivity" => {
+                    last_model(&mut options.models).reflectivity = number(&mut args, &arg)
+                }
+                "--transparency" => {
+                    last_model(&mut options.models).transparency = number(&mut args, &arg)
+                }
+                "--ior" => last_model(&mut options.models).ior = number(&mut args, &arg),
+                "--albedo" => last_model(&mut options.models).albedo = Some(value(&mut args, &arg)),
+                "--normal-map" => {
+                    last_model(&mut options.models).normal_map = Some(value(&mut args, &arg))
+                }
+                "--roughness-map" => {
+                    last_model(&mut options.models).roughness_map = Some(value(&mut args, &arg))
+                }
+                "--env" => options.environment = Some(value(&mut args, &arg)),
+                "--env-rotation" => {
+                    options.environment_rotation = number(&mut args, &arg).to_radians()
+                }
+                "--tonemap" => {
+                    let name = value(&mut args, &arg);
+                    options.tone_mapping.operator = match Operator::from_name(&name) {
+                        Some(operator) => operator,
+                        None => fail(&format!("unknown tone mapping operator {}", name)),
+                    }
+                }
+                "--exposure" => options.tone_mapping.exposure = number(&mut args, &arg),
+                "--gamma" => options.tone_mapping.gamma = number(&mut args, &arg),
+                "--post" => {
+                    options.post = match Effect::parse_list(&value(&mut args, &arg)) {
+                        Ok(effects) => effects,
+                        Err(message) => fail(&message),
+                    }
+                }
+                "--max-depth" => options.max_depth = integer(&mut args, &arg),
+                "--fragment" => options.compute = false,
+                "--workgroup" => {
+                    let [width, height] = size(&mut args, &arg);
+                    options.workgroup_size = WorkgroupSize { width, height };
+                }
+                "--tile-size" => options.tile_size = integer(&mut args, &arg),
+                "--tiles-per-submit" => options.tiles_per_submit = integer(&mut args, &arg),
+                "--time" => options.start_time = number(&mut args, &arg).into(),
+                "--speed" => options.speed = number(&mut args, &arg).into(),
+                "--fixed-step" => match number(&mut args, &arg) {
+                    step if step > 0.0 => options.step = Step::Fixed(step.into()),
+                    step => fail(&format!("invalid step for {}: {}", arg, step)),
+                },
+                "--paused" => options.paused = true,
+                "--capture" => options.capture = Some(integer(&mut args, &arg)),
+                "--capture-fps" => match number(&mut args, &arg) {
+                    fps if fps > 0.0 => options.capture_fps = fps,
+                    fps => fail(&format!("invalid frame rate for {}: {}", arg, fps)),
+                },
+                "--capture-dir" => options.capture_dir = value(&mut args, &arg),
+                "--reference" => options.reference = Some(value(&mut args, &arg)),
+                "--size" => options.reference_size = size(&mut args, &arg),
+                "--threads" => options.threads = integer(&mut args, &arg),
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ => fail(&format!("unknown argument {}", arg)),
+            }
+        }
+        last_model(&mut options.models);
+
+        options
+    }
+}
@@ -0,0 +1,616 @@
+use std::fmt;
+use std::sync::Arc;
+
+use image;
+use vulkano::buffer::{BufferAccess, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet};
+use vulkano::device::{Device, Queue};
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass};
+use vulkano::image::{AttachmentImage, Dimensions, ImageUsage, ImageViewAccess, ImmutableImage};
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use vulkano::sync::{now, GpuFuture};
+
+use quad::{self, Vertex};
+use shaders;
+
+/**
+ * A fullscreen effect applied to the HDR image before tone mapping
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Effect {
+    // Bright parts of the image bleed into their surroundings. Only the
+    // brightness above the threshold glows, blurred over the given number of
+    // halved resolutions.
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        levels: u32,
+    },
+    // Darkens the corners, 0 leaves them untouched and 1 makes them black
+    Vignette { strength: f32 },
+    // Red and blue are shifted apart towards the edges, by up to this many pixels
+    ChromaticAberration { strength: f32 },
+    Fxaa,
+    // Looks up the color in a 3D LUT
+    ColorGrading { lut: Lut, strength: f32 },
+}
+
+/**
+ * A color grading LUT, loaded from a strip of size * size by size pixels with
+ * blue going from one square to the next. The texels are reordered into the
+ * layout of a 3D image, with red along x, green along y and blue along z.
+ */
+#[derive(Clone, PartialEq)]
+pub struct Lut {
+    pub path: String,
+    size: u32,
+    texels: Vec<[u8; 4]>,
+}
+
+impl Lut {
+    pub fn load(path: &str) -> Result<Lut, String> {
+        let strip = image::open(path)
+            .map_err(|e| format!("failed to load color grading LUT {}: {}", path, e))?
+            .to_rgba();
+        let size = strip.height();
+        if size == 0 || strip.width() != size * size {
+            return Err(format!(
+                "color grading LUT {} is not {} pixels wide",
+                path,
+                size * size
+            ));
+        }
+
+        let mut texels = Vec::with_capacity((size * size * size) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    texels.push(strip.get_pixel(b * size + r, g).data);
+                }
+            }
+        }
+        Ok(Lut {
+            path: path.to_string(),
+            size,
+            texels,
+        })
+    }
+}
+
+impl fmt::Debug for Lut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Lut({})", self.path)
+    }
+}
+
+fn parameter(parameters: &[&str], i: usize, default: f32) -> Result<f32, String> {
+    match parameters.get(i) {
+        Some(p) => p
+            .parse()
+            .map_err(|_| format!("invalid post-processing parameter {}", p)),
+        None => Ok(default),
+    }
+}
+
+impl Effect {
+    /**
+     * The chain used when nothing was given on the command line
+     */
+    pub fn defaults() -> Vec<Effect> {
+        vec![
+            Effect::Bloom {
+                threshold: 1.0,
+                intensity: 0.3,
+                levels: 5,
+            },
+            Effect::Fxaa,
+            Effect::Vignette { strength: 0.3 },
+        ]
+    }
+
+    /**
+     * Parses a comma separated list of effects like
+     * `bloom:1.0:0.3,fxaa,grade:warm.png`, where the parameters of an effect
+     * follow its name after colons. `none` is the empty chain. Color grading
+     * LUTs are loaded here, so a broken one is reported with the list.
+     */
+    pub fn parse_list(list: &str) -> Result<Vec<Effect>, String> {
+        if list == "none" {
+            return Ok(vec![]);
+        }
+        list.split(',').map(Effect::parse).collect()
+    }
+
+    fn parse(spec: &str) -> Result<Effect, String> {
+        let mut parts = spec.split(':');
+        let name = parts.next().unwrap();
+        let parameters: Vec<&str> = parts.collect();
+
+        let effect = match name {
+            "bloom" => Effect::Bloom {
+                threshold: parameter(&parameters, 0, 1.0)?,
+                intensity: parameter(&parameters, 1, 0.3)?,
+                levels: (parameter(&parameters, 2, 5.0)? as u32).max(1),
+            },
+            "vignette" => Effect::Vignette {
+                strength: parameter(&parameters, 0, 0.3)?,
+            },
+            "chromatic" => Effect::ChromaticAberration {
+                strength: parameter(&parameters, 0, 2.0)?,
+            },
+            "fxaa" => Effect::Fxaa,
+            "grade" => match parameters.first() {
+                Some(lut) => Effect::ColorGrading {
+                    lut: Lut::load(lut)?,
+                    strength: parameter(&parameters, 1, 1.0)?,
+                },
+                None => return Err("grade needs a LUT image".to_string()),
+            },
+            _ => return Err(format!("unknown post-processing effect {}", name)),
+        };
+        Ok(effect)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct PushData {
+    resolution: [f32; 2],
+    parameters: [f32; 2],
+}
+
+/**
+ * One fullscreen draw into an intermediate image
+ */
+struct Pass {
+    pipeline: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    set: Arc<DescriptorSet + Send + Sync>,
+    framebuffer: Arc<FramebufferAbstract + Send + Sync>,
+    dynamic_state: DynamicState,
+    push_data: PushData,
+}
+
+struct Pipelines {
+    bloom_downsample: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    bloom_upsample: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    bloom_composite: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    vignette: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    chromatic_aberration: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    fxaa: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    color_grading: Arc<GraphicsPipelineAbstract + Send + Sync>,
+}
+
+// The shaders all have different types, so this can't be a function
+macro_rules! pipeline {
+    ($device:expr, $render_pass:expr, $vs:expr, $fs:expr) => {
+        Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input_single_buffer::<Vertex>()
+                .vertex_shader($vs.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader($fs.main_entry_point(), ())
+                .render_pass(Subpass::from($render_pass.clone(), 0).unwrap())
+                .build($device.clone())
+                .unwrap(),
+        ) as Arc<GraphicsPipelineAbstract + Send + Sync>
+    };
+}
+
+/**
+ * The post-processing effects, drawn in order between the scene and the tone
+ * mapping pass. Effects ping-pong between two images of the window size, bloom
+ * additionally uses a pyramid of smaller images.
+ */
+pub struct PostChain {
+    device: Arc<Device>,
+    render_pass: Arc<RenderPassAbstract + Send + Sync>,
+    pipelines: Pipelines,
+    sampler: Arc<Sampler>,
+    quad: Arc<CpuAccessibleBuffer<[Vertex]>>,
+    effects: Vec<Effect>,
+    // The lookup table of every color grading effect, by index into `effects`
+    luts: Vec<Option<Arc<ImmutableImage<Format>>>>,
+    format: Format,
+    passes: Vec<Pass>,
+    output: Option<Arc<AttachmentImage>>,
+}
+
+impl PostChain {
+    /**
+     * Compiles the pipelines and uploads the color grading LUTs. The returned
+     * future has to finish before the chain is drawn.
+     */
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        format: Format,
+        effects: Vec<Effect>,
+    ) -> (PostChain, Box<GpuFuture>) {
+        let render_pass = Arc::new(
+            single_pass_renderpass!(device.clone(),
+            attachments: {
+                color: {
+                    load: DontCare,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {}
+            }
+        ).unwrap(),
+        ) as Arc<RenderPassAbstract + Send + Sync>;
+
+        let vs = shaders::get_vertex_shader(device.clone());
+        let pipelines = Pipelines {
+            bloom_downsample: pipeline!(
+                device,
+                render_pass,
+                vs,
+                shaders::get_bloom_downsample_shader(device.clone())
+            ),
+            bloom_upsample: pipeline!(
+                device,
+                render_pass,
+                vs,
+                shaders::get_bloom_upsample_shader(device.clone())
+            ),
+            bloom_composite: pipeline!(
+                device,
+                render_pass,
+                vs,
+                shaders::get_bloom_composite_shader(device.clone())
+            ),
+            vignette: pipeline!(
+                device,
+                render_pass,
+                vs,
+                shaders::get_vignette_shader(device.clone())
+            ),
+            chromatic_aberration: pipeline!(
+                device,
+                render_pass,
+                vs,
+                shaders::get_chromatic_aberration_shader(device.clone())
+            ),
+            fxaa: pipeline!(
+                device,
+                render_pass,
+                vs,
+                shaders::get_fxaa_shader(device.clone())
+            ),
+            color_grading: pipeline!(
+                device,
+                render_pass,
+                vs,
+                shaders::get_color_grading_shader(device.clone())
+            ),
+        };
+
+        let mut future = Box::new(now(device.clone())) as Box<GpuFuture>;
+        let mut luts = Vec::with_capacity(effects.len());
+        for effect in &effects {
+            luts.push(match *effect {
+                Effect::ColorGrading { ref lut, .. } => {
+                    let (image, f) = ImmutableImage::from_iter(
+                        lut.texels.iter().cloned(),
+                        Dimensions::Dim3d {
+                            width: lut.size,
+                            height: lut.size,
+                            depth: lut.size,
+                        },
+                        Format::R8G8B8A8Unorm,
+                        queue.clone(),
+                    ).expect("Failed to create color grading LUT");
+                    future = Box::new(future.join(f));
+                    Some(image)
+                }
+                _ => None,
+            });
+        }
+
+        // Blurs must not wrap around to the other side of the image
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        ).expect("Failed to create post-processing sampler");
+
+        let chain = PostChain {
+            quad: quad::fullscreen_quad(device.clone()),
+            device,
+            render_pass,
+            pipelines,
+            sampler,
+            effects,
+            luts,
+            format,
+            passes: vec![],
+            output: None,
+        };
+        (chain, future)
+    }
+
+    fn image(&self, dimensions: [u32; 2]) -> Arc<AttachmentImage> {
+        AttachmentImage::with_usage(
+            self.device.clone(),
+            dimensions,
+            self.format,
+            ImageUsage {
+                color_attachment: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+        ).expect("Failed to create post-processing image")
+    }
+
+    fn pass<I>(
+        &self,
+        pipeline: &Arc<GraphicsPipelineAbstract + Send + Sync>,
+        set: Arc<DescriptorSet + Send + Sync>,
+        target: I,
+        dimensions: [u32; 2],
+        parameters: [f32; 2],
+    ) -> Pass
+    where
+        I: ImageViewAccess + Send + Sync + 'static,
+    {
+        let resolution = [dimensions[0] as f32, dimensions[1] as f32];
+        Pass {
+            pipeline: pipeline.clone(),
+            set,
+            framebuffer: Arc::new(
+                Framebuffer::start(self.render_pass.clone())
+                    .add(target)
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            ),
+            dynamic_state: DynamicState {
+                line_width: None,
+                viewports: Some(vec![Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: resolution,
+                    depth_range: 0.0..1.0,
+                }]),
+                scissors: None,
+            },
+            push_data: PushData {
+                resolution,
+                parameters,
+            },
+        }
+    }
+
+    fn set<I>(
+        &self,
+        pipeline: &Arc<GraphicsPipelineAbstract + Send + Sync>,
+        image: I,
+    ) -> Arc<DescriptorSet + Send + Sync>
+    where
+        I: ImageViewAccess + Send + Sync + 'static,
+    {
+        Arc::new(
+            PersistentDescriptorSet::start(pipeline.clone(), 0)
+                .add_sampled_image(image, self.sampler.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        )
+    }
+
+    fn set2<I, J>(
+        &self,
+        pipeline: &Arc<GraphicsPipelineAbstract + Send + Sync>,
+        first: I,
+        second: J,
+    ) -> Arc<DescriptorSet + Send + Sync>
+    where
+        I: ImageViewAccess + Send + Sync + 'static,
+        J: ImageViewAccess + Send + Sync + 'static,
+    {
+        Arc::new(
+            PersistentDescriptorSet::start(pipeline.clone(), 0)
+                .add_sampled_image(first, self.sampler.clone())
+                .unwrap()
+                .add_sampled_image(second, self.sampler.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        )
+    }
+
+    /**
+     * Appends the passes of a bloom effect reading `source` and writing `target`
+     */
+    fn bloom(
+        &self,
+        passes: &mut Vec<Pass>,
+        source: &Arc<AttachmentImage>,
+        target: &Arc<AttachmentImage>,
+        threshold: f32,
+        intensity: f32,
+        levels: u32,
+    ) {
+        let dimensions = target.dimensions();
+        let sizes: Vec<[u32; 2]> = (1..levels + 1)
+            .map(|level| [(dimensions[0] >> level).max(1), (dimensions[1] >> level).max(1)])
+            .collect();
+        let down: Vec<_> = sizes.iter().map(|&size| self.image(size)).collect();
+        let up: Vec<_> = sizes.iter().map(|&size| self.image(size)).collect();
+
+        let pipelines = &self.pipelines;
+        for (level, &size) in sizes.iter().enumerate() {
+            let (input, parameters) = if level == 0 {
+                (source, [threshold, 1.0])
+            } else {
+                (&down[level - 1], [0.0, 0.0])
+            };
+            let set = self.set(&pipelines.bloom_downsample, input.clone());
+            passes.push(self.pass(
+                &pipelines.bloom_downsample,
+                set,
+                down[level].clone(),
+                size,
+                parameters,
+            ));
+        }
+
+        // The smallest level is already as blurry as it gets, every larger one
+        // adds its own detail to the upsampled result below it
+        let last = sizes.len() - 1;
+        for level in (0..last).rev() {
+            let low = if level + 1 == last {
+                &down[last]
+            } else {
+                &up[level + 1]
+            };
+            let set = self.set2(
+                &pipelines.bloom_upsample,
+                low.clone(),
+                down[level].clone(),
+            );
+            passes.push(self.pass(
+                &pipelines.bloom_upsample,
+                set,
+                up[level].clone(),
+                sizes[level],
+                [0.0, 0.0],
+            ));
+        }
+
+        let bloom = if last == 0 { &down[0] } else { &up[0] };
+        let set = self.set2(&pipelines.bloom_composite, source.clone(), bloom.clone());
+        passes.push(self.pass(
+            &pipelines.bloom_composite,
+            set,
+            target.clone(),
+            dimensions,
+            [intensity, 0.0],
+        ));
+    }
+
+    /**
+     * Recreates the intermediate images for a new window size. `input` is the
+     * HDR image the scene is rendered to.
+     */
+    pub fn resize(&mut self, input: Arc<AttachmentImage>, dimensions: [u32; 2]) {
+        let mut passes = vec![];
+        let mut source = input;
+
+        if !self.effects.is_empty() {
+            let targets = [self.image(dimensions), self.image(dimensions)];
+            let pipelines = &self.pipelines;
+
+            for (i, effect) in self.effects.iter().enumerate() {
+                let target = &targets[i % 2];
+                match *effect {
+                    Effect::Bloom {
+                        threshold,
+                        intensity,
+                        levels,
+                    } => self.bloom(
+                        &mut passes,
+                        &source,
+                        target,
+                        threshold,
+                        intensity,
+                        levels,
+                    ),
+                    Effect::Vignette { strength } => {
+                        let set = self.set(&pipelines.vignette, source.clone());
+                        passes.push(self.pass(
+                            &pipelines.vignette,
+                            set,
+                            target.clone(),
+                            dimensions,
+                            [strength, 0.0],
+                        ));
+                    }
+                    Effect::ChromaticAberration { strength } => {
+                        let set = self.set(&pipelines.chromatic_aberration, source.clone());
+                        passes.push(self.pass(
+                            &pipelines.chromatic_aberration,
+                            set,
+                            target.clone(),
+                            dimensions,
+                            [strength, 0.0],
+                        ));
+                    }
+                    Effect::Fxaa => {
+                        let set = self.set(&pipelines.fxaa, source.clone());
+                        passes.push(self.pass(
+                            &pipelines.fxaa,
+                            set,
+                            target.clone(),
+                            dimensions,
+                            [0.0, 0.0],
+                        ));
+                    }
+                    Effect::ColorGrading { strength, .. } => {
+                        let lut = self.luts[i].clone().unwrap();
+                        let set = self.set2(&pipelines.color_grading, source.clone(), lut);
+                        passes.push(self.pass(
+                            &pipelines.color_grading,
+                            set,
+                            target.clone(),
+                            dimensions,
+                            [strength, 0.0],
+                        ));
+                    }
+                }
+                source = target.clone();
+            }
+        }
+
+        self.passes = passes;
+        self.output = Some(source);
+    }
+
+    /**
+     * The image holding the result of the last effect, or the input if the
+     * chain is empty
+     */
+    pub fn output(&self) -> Arc<AttachmentImage> {
+        self.output
+            .clone()
+            .expect("Post-processing chain was not sized yet")
+    }
+
+    /**
+     * Records all passes of the chain
+     */
+    pub fn draw(&self, mut builder: AutoCommandBufferBuilder) -> AutoCommandBufferBuilder {
+        for pass in &self.passes {
+            builder = builder
+                .begin_render_pass(pass.framebuffer.clone(), false, vec![ClearValue::None])
+                .unwrap()
+                .draw(
+                    pass.pipeline.clone(),
+                    &pass.dynamic_state,
+                    vec![self.quad.clone() as Arc<BufferAccess + Send + Sync>],
+                    pass.set.clone(),
+                    pass.push_data,
+                ).unwrap()
+                .end_render_pass()
+                .unwrap();
+        }
+        builder
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/**
+ * A shader with all includes pasted in
+ */
+pub struct Source {
+    pub code: String,
+    // Every file that was read, in the order of their source string numbers
+    pub files: Vec<PathBuf>,
+}
+
+/**
+ * Resolves `#include "file"` directives, relative to the including file. Every
+ * file is only included once, so modules don't need include guards. `#line`
+ * directives keep compiler errors pointing at the original lines, with the
+ * index into `files` as the source string number.
+ */
+pub fn preprocess(path: &Path) -> io::Result<Source> {
+    let mut source = Source {
+        code: String::new(),
+        files: vec![],
+    };
+    include(path, &mut source)?;
+    Ok(source)
+}
+
+fn include(path: &Path, source: &mut Source) -> io::Result<()> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let number = source.files.len();
+    source.files.push(path.to_path_buf());
+
+    for (i, line) in text.lines().enumerate() {
+        let directive = line.trim();
+        if !directive.starts_with("#include") {
+            source.code.push_str(line);
+            source.code.push('\n');
+            continue;
+        }
+
+        let name = directive["#include".len()..].trim();
+        if name.len() < 2 || !name.starts_with('"') || !name.ends_with('"') {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{}:{}: expected #include \"file\"", path.display(), i + 1),
+            ));
+        }
+        let included = path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(&name[1..name.len() - 1]);
+
+        if source.files.contains(&included) {
+            // Keeps the following lines numbered correctly
+            source.code.push('\n');
+        } else {
+            source.code.push_str(&format!("#line 1 {}\n", source.files.len()));
+            include(&included, source)?;
+            source.code.push_str(&format!("#line {} {}\n", i + 2, number));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::device::Device;
+
+#[derive(Debug, Clone)]
+pub struct Vertex {
+    position: [f32; 2],
+}
+impl_vertex!(Vertex, position);
+
+/**
+ * Two triangles covering the whole viewport, so the fragment shader runs
+ * once for every pixel
+ */
+pub fn fullscreen_quad(device: Arc<Device>) -> Arc<CpuAccessibleBuffer<[Vertex]>> {
+    CpuAccessibleBuffer::from_iter(
+        device,
+        BufferUsage::all(),
+        [
+            Vertex {
+                position: [-1.0, -1.0],
+            },
+            Vertex {
+                position: [1.0, -1.0],
+            },
+            Vertex {
+                position: [-1.0, 1.0],
+            },
+            Vertex {
+                position: [1.0, 1.0],
+            },
+            Vertex {
+                position: [1.0, -1.0],
+            },
+            Vertex {
+                position: [-1.0, 1.0],
+            },
+        ]
+            .iter()
+            .cloned(),
+    ).expect("failed to create buffer")
+}
@@ -0,0 +1,521 @@
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+
+use image::{Rgb, RgbImage};
+
+use camera::Camera;
+use material::Material;
+use scene::Scene;
+use tonemap::ToneMapping;
+
+// The constants below must match the ones in shaders/lib
+const EPSILON: f32 = 0.00001;
+const MAX_DIST: f32 = 20.0;
+const SHADOW_BIAS: f32 = 0.0005;
+const AREA_LIGHT_SAMPLES: u32 = 8;
+const NEAR_PLANE: f32 = 0.01;
+const FOV: f32 = PI / 2.0;
+
+type Vec3 = [f32; 3];
+
+fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn mul(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] * b[0], a[1] * b[1], a[2] * b[2]]
+}
+
+fn scale(a: Vec3, s: f32) -> Vec3 {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: Vec3) -> Vec3 {
+    scale(a, 1.0 / dot(a, a).sqrt())
+}
+
+fn reflect(v: Vec3, n: Vec3) -> Vec3 {
+    sub(v, scale(n, 2.0 * dot(n, v)))
+}
+
+// Same as GLSL's refract(), the zero vector on total internal reflection
+fn refract(v: Vec3, n: Vec3, eta: f32) -> Vec3 {
+    let cos_i = dot(n, v);
+    let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+    if k < 0.0 {
+        [0.0; 3]
+    } else {
+        sub(scale(v, eta), scale(n, eta * cos_i + k.sqrt()))
+    }
+}
+
+fn xyz(v: [f32; 4]) -> Vec3 {
+    [v[0], v[1], v[2]]
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c > 0.04045 {
+        ((c + 0.055) / 1.055).powf(2.4)
+    } else {
+        c / 12.92
+    }
+}
+
+// The i-th of n points of the Hammersley set, evenly covering the unit square.
+// The shader picks points on area lights at random instead.
+fn hammersley(i: u32, n: u32) -> [f32; 2] {
+    [
+        (i as f32 + 0.5) / n as f32,
+        i.reverse_bits() as f32 / 4_294_967_296.0,
+    ]
+}
+
+struct Hit {
+    position: Vec3,
+    // Faces the ray
+    normal: Vec3,
+    front_face: bool,
+    material: Material,
+}
+
+/**
+ * Settings of a reference rendering
+ */
+#[derive(Clone, Copy)]
+pub struct Settings {
+    pub dimensions: [u32; 2],
+    pub camera: Camera,
+    // Maximum number of reflections and refractions along a ray
+    pub max_depth: u32,
+    // Rotation of the environment map around the Y axis in radians
+    pub environment_rotation: f32,
+    pub tone_mapping: ToneMapping,
+    pub threads: usize,
+}
+
+/**
+ * Renders the scene on the CPU like the default mode of the shader, for
+ * checking GPU images on machines without Vulkan. Only the mesh is traced,
+ * the distance field objects and light from the environment map are left
+ * out, and there is no post-processing.
+ */
+pub fn render(scene: Arc<Scene>, settings: Settings) -> RgbImage {
+    let [width, height] = settings.dimensions;
+    let next_row = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = channel();
+
+    let threads: Vec<_> = (0..settings.threads.max(1))
+        .map(|_| {
+            let scene = scene.clone();
+            let next_row = next_row.clone();
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let tracer = Tracer {
+                    scene: &scene,
+                    settings,
+                };
+                loop {
+                    let y = next_row.fetch_add(1, Ordering::Relaxed) as u32;
+                    if y >= height {
+                        break;
+                    }
+                    let row: Vec<Vec3> = (0..width).map(|x| tracer.trace_pixel(x, y)).collect();
+                    tx.send((y, row)).unwrap();
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+
+    let mut image = RgbImage::new(width, height);
+    for (y, row) in rx {
+        for (x, color) in row.into_iter().enumerate() {
+            let [r, g, b] = settings.tone_mapping.apply(color);
+            let [r, g, b] = [r * 255.0, g * 255.0, b * 255.0];
+            image.put_pixel(
+                x as u32,
+                y,
+                Rgb([r.round() as u8, g.round() as u8, b.round() as u8]),
+            );
+        }
+    }
+    for thread in threads {
+        thread.join().expect("Reference render thread panicked");
+    }
+    image
+}
+
+struct Tracer<'a> {
+    scene: &'a Scene,
+    settings: Settings,
+}
+
+impl<'a> Tracer<'a> {
+    // getEdges() and getRay() in shaders/lib/camera.glsl
+    fn trace_pixel(&self, x: u32, y: u32) -> Vec3 {
+        let [width, height] = self.settings.dimensions;
+        let camera = self.settings.camera;
+
+        let up = [0.0, 1.0, 0.0];
+        let right = cross(camera.direction, up);
+        let plane_x_half = (FOV / 2.0).sin() * NEAR_PLANE;
+        let plane_y_half = plane_x_half * height as f32 / width as f32;
+
+        let dx = scale(right, plane_x_half);
+        let dy = scale(up, plane_y_half);
+        let dz = scale(camera.direction, NEAR_PLANE);
+        let upper_left = add(sub(add(camera.position, dy), dx), dz);
+        let upper_right = add(add(add(camera.position, dx), dy), dz);
+        let lower_left = add(sub(sub(camera.position, dx), dy), dz);
+
+        // Through the pixel center, like gl_FragCoord
+        let u = (x as f32 + 0.5) / width as f32;
+        let v = (y as f32 + 0.5) / height as f32;
+        let point = add(
+            add(upper_left, scale(sub(upper_right, upper_left), u)),
+            scale(sub(lower_left, upper_left), v),
+        );
+
+        self.render(camera.position, normalize(sub(point, camera.position)), 0)
+    }
+
+    // render() in shaders/lib/render.glsl, with recursion instead of a stack
+    fn render(&self, o: Vec3, v: Vec3, depth: u32) -> Vec3 {
+        let hit = match self.intersect(o, v) {
+            Some(hit) => hit,
+            None => return self.background(v),
+        };
+        let (p, n, mat) = (hit.position, hit.normal, hit.material);
+
+        // fresnelSplit() in shaders/lib/materials.glsl
+        let mut kr = mat.reflectivity;
+        let mut kt = 0.0;
+        let mut refracted = [0.0; 3];
+        if mat.transparency > 0.0 {
+            let eta = if hit.front_face {
+                1.0 / mat.ior
+            } else {
+                mat.ior
+            };
+            refracted = refract(v, n, eta);
+
+            let mut f = 1.0;
+            if refracted != [0.0; 3] {
+                let cos_theta = if hit.front_face {
+                    -dot(v, n)
+                } else {
+                    -dot(refracted, n)
+                };
+                let r0 = (1.0 - mat.ior) / (1.0 + mat.ior);
+                let f0 = r0 * r0;
+                f = f0 + (1.0 - f0) * (1.0 - cos_theta.max(0.0).min(1.0)).powi(5);
+            }
+            kr += mat.transparency * f;
+            kt = mat.transparency * (1.0 - f);
+        }
+
+        let mut color = scale(self.shade(p, n, v, &mat), 1.0 - kr - kt);
+        if depth >= self.settings.max_depth {
+            return color;
+        }
+
+        if kt > 0.0 {
+            let weight = scale(xyz(mat.color), kt);
+            let o = sub(p, scale(n, SHADOW_BIAS));
+            color = add(color, mul(weight, self.render(o, refracted, depth + 1)));
+        }
+        if kr > 0.0 {
+            let o = add(p, scale(n, SHADOW_BIAS));
+            color = add(color, scale(self.render(o, reflect(v, n), depth + 1), kr));
+        }
+        color
+    }
+
+    // shade() in shaders/lib/render.glsl
+    fn shade(&self, p: Vec3, n: Vec3, v: Vec3, mat: &Material) -> Vec3 {
+        let mut color = [0.0; 3];
+        for light in &self.scene.lights {
+            let samples = if light.is_area() {
+                AREA_LIGHT_SAMPLES
+            } else {
+                1
+            };
+            let mut light_color = [0.0; 3];
+
+            for j in 0..samples {
+                let xi = if light.is_area() {
+                    hammersley(j, samples)
+                } else {
+                    [0.5, 0.5]
+                };
+                let (radiance, light_dir, light_dist) = light.sample(p, xi);
+
+                if dot(light_dir, n) <= 0.0 {
+                    continue;
+                }
+                let origin = add(p, scale(n, SHADOW_BIAS));
+                if self.occluded(origin, light_dir, light_dist.min(MAX_DIST)) {
+                    continue;
+                }
+
+                light_color = add(light_color, mul(phong(n, v, mat, light_dir), radiance));
+            }
+
+            color = add(color, scale(light_color, 1.0 / samples as f32));
+        }
+        color
+    }
+
+    // background() in shaders/lib/environment.glsl
+    fn background(&self, v: Vec3) -> Vec3 {
+        let environment = &self.scene.environment;
+        let (sin, cos) = (-self.settings.environment_rotation).sin_cos();
+        let d = [v[0] * cos + v[2] * sin, v[1], -v[0] * sin + v[2] * cos];
+
+        let u = d[0].atan2(-d[2]) / (2.0 * PI) + 0.5;
+        let t = d[1].max(-1.0).min(1.0).acos() / PI;
+        let x = ((u * environment.width as f32) as u32).min(environment.width - 1);
+        let y = ((t * environment.height as f32) as u32).min(environment.height - 1);
+        xyz(environment.pixels[(y * environment.width + x) as usize])
+    }
+
+    fn triangle(&self, indices: [u32; 4]) -> [Vec3; 3] {
+        let vertices = &self.scene.object.vertices;
+        [
+            xyz(vertices[indices[0] as usize]),
+            xyz(vertices[indices[1] as usize]),
+            xyz(vertices[indices[2] as usize]),
+        ]
+    }
+
+    /**
+     * Walks the BVH from the root, which is the last node. Returns the
+     * distance, the barycentric coordinates and the triangle of the closest
+     * hit, or of any hit if `any_hit` is set.
+     */
+    fn traverse(
+        &self,
+        o: Vec3,
+        v: Vec3,
+        max_dist: f32,
+        any_hit: bool,
+    ) -> Option<(f32, [f32; 2], [u32; 4])> {
+        let nodes = &self.scene.object.bvh;
+        if nodes.is_empty() {
+            return None;
+        }
+        let dir_inv = [1.0 / v[0], 1.0 / v[1], 1.0 / v[2]];
+
+        let mut closest = None;
+        let mut dist = max_dist;
+        let mut stack = vec![nodes.len() as u32 - 1];
+        while let Some(index) = stack.pop() {
+            let node = &nodes[index as usize];
+            if let Some(indices) = node.triangle() {
+                if let Some((t, barycentric)) = intersect_triangle(o, v, self.triangle(indices)) {
+                    if t < dist {
+                        dist = t;
+                        closest = Some((t, barycentric, indices));
+                        if any_hit {
+                            break;
+                        }
+                    }
+                }
+            } else if let Some((bounding_box, left, right)) = node.children() {
+                if hits_box(o, dir_inv, &bounding_box, dist) {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        closest
+    }
+
+    // intersect() and meshSurface() in shaders/lib/scene.glsl
+    fn intersect(&self, o: Vec3, v: Vec3) -> Option<Hit> {
+        let (t, [b1, b2], indices) = self.traverse(o, v, MAX_DIST, false)?;
+        let [v0, v1, v2] = self.triangle(indices);
+
+        let mut mat = self.scene.materials[indices[3] as usize];
+        let uvs = &self.scene.object.uvs;
+        let uv = |i: u32| uvs.get(i as usize).cloned().unwrap_or([0.0, 0.0]);
+        let (uv0, uv1, uv2) = (uv(indices[0]), uv(indices[1]), uv(indices[2]));
+        let b0 = 1.0 - b1 - b2;
+        let uv = [
+            b0 * uv0[0] + b1 * uv1[0] + b2 * uv2[0],
+            b0 * uv0[1] + b1 * uv1[1] + b2 * uv2[1],
+        ];
+
+        if mat.albedo_texture >= 0 {
+            let albedo = self.sample_texture(mat.albedo_texture, uv);
+            mat.color = [
+                srgb_to_linear(albedo[0]),
+                srgb_to_linear(albedo[1]),
+                srgb_to_linear(albedo[2]),
+                albedo[3],
+            ];
+        }
+
+        let e1 = sub(v1, v0);
+        let e2 = sub(v2, v0);
+        let geometric = cross(e1, e2);
+        let mut n = normalize(geometric);
+
+        let normals = &self.scene.object.normals;
+        let normal = |i: u32| match normals.get(i as usize) {
+            Some(&[x, y, z, _]) => [x, y, z],
+            None => [0.0, 0.0, 0.0],
+        };
+        let (n0, n1, n2) = (normal(indices[0]), normal(indices[1]), normal(indices[2]));
+        let smooth = add(add(scale(n0, b0), scale(n1, b1)), scale(n2, b2));
+        if dot(smooth, smooth).sqrt() > EPSILON {
+            n = scale(normalize(smooth), (dot(smooth, n) + EPSILON).signum());
+        }
+
+        let duv1 = [uv1[0] - uv0[0], uv1[1] - uv0[1]];
+        let duv2 = [uv2[0] - uv0[0], uv2[1] - uv0[1]];
+        let det = duv1[0] * duv2[1] - duv1[1] * duv2[0];
+        if mat.normal_texture >= 0 && det.abs() > EPSILON {
+            let tangent = scale(sub(scale(e1, duv2[1]), scale(e2, duv1[1])), 1.0 / det);
+            let bitangent = scale(sub(scale(e2, duv1[0]), scale(e1, duv2[0])), 1.0 / det);
+            let tangent = normalize(sub(tangent, scale(n, dot(n, tangent))));
+            let bitangent = normalize(sub(
+                sub(bitangent, scale(n, dot(n, bitangent))),
+                scale(tangent, dot(tangent, bitangent)),
+            ));
+
+            let mapped = self.sample_texture(mat.normal_texture, uv);
+            let m = [
+                mapped[0] * 2.0 - 1.0,
+                mapped[1] * 2.0 - 1.0,
+                mapped[2] * 2.0 - 1.0,
+            ];
+            n = normalize(add(
+                add(scale(tangent, m[0]), scale(bitangent, m[1])),
+                scale(n, m[2]),
+            ));
+        }
+
+        // The geometric normal decides the side, normal maps only shade
+        let front_face = dot(geometric, v) < 0.0;
+        if !front_face {
+            n = scale(n, -1.0);
+        }
+
+        Some(Hit {
+            position: add(o, scale(v, t)),
+            normal: n,
+            front_face,
+            material: mat,
+        })
+    }
+
+    fn occluded(&self, o: Vec3, v: Vec3, max_dist: f32) -> bool {
+        self.traverse(o, v, max_dist, true).is_some()
+    }
+
+    // Bilinear and repeating, like the texture sampler
+    fn sample_texture(&self, layer: i32, uv: [f32; 2]) -> [f32; 4] {
+        let textures = &self.scene.textures;
+        let (width, height) = (textures.width as i64, textures.height as i64);
+        let x = uv[0] * width as f32 - 0.5;
+        let y = uv[1] * height as f32 - 0.5;
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+        let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+
+        let texel = |x: i64, y: i64| {
+            let x = x.rem_euclid(width);
+            let y = y.rem_euclid(height);
+            let index = (layer as i64 * height + y) * width + x;
+            textures.pixels[index as usize]
+        };
+
+        let mut color = [0.0; 4];
+        for (i, c) in color.iter_mut().enumerate() {
+            let top = texel(x0, y0)[i] as f32 * (1.0 - fx) + texel(x0 + 1, y0)[i] as f32 * fx;
+            let bottom =
+                texel(x0, y0 + 1)[i] as f32 * (1.0 - fx) + texel(x0 + 1, y0 + 1)[i] as f32 * fx;
+            *c = (top * (1.0 - fy) + bottom * fy) / 255.0;
+        }
+        color
+    }
+}
+
+// phong() in shaders/lib/render.glsl
+fn phong(n: Vec3, v: Vec3, mat: &Material, light_dir: Vec3) -> Vec3 {
+    let l = dot(light_dir, n).max(0.0);
+    let mut s = 0.0;
+
+    if l > 0.0 {
+        let refl_dir = reflect(scale(light_dir, -1.0), n);
+        let angle = dot(refl_dir, v).max(0.0);
+        s = angle.powf(4.0);
+    }
+
+    add(scale(xyz(mat.color), l), [s, s, s])
+}
+
+// Möller-Trumbore, testIntersection() in shaders/lib/intersection.glsl
+fn intersect_triangle(o: Vec3, v: Vec3, [v0, v1, v2]: [Vec3; 3]) -> Option<(f32, [f32; 2])> {
+    let edge1 = sub(v1, v0);
+    let edge2 = sub(v2, v0);
+    let h = cross(v, edge2);
+    let a = dot(edge1, h);
+    // Parallel ray, relative to the size of the triangle so that small
+    // triangles of subdivided models are still hit
+    if a * a < EPSILON * EPSILON * dot(edge1, edge1) * dot(edge2, edge2) {
+        return None;
+    }
+
+    let f = 1.0 / a;
+    let s = sub(o, v0);
+    let u = f * dot(s, h);
+    if u < 0.0 || u > 1.0 {
+        return None;
+    }
+
+    let q = cross(s, edge1);
+    let w = f * dot(v, q);
+    if w < 0.0 || u + w > 1.0 {
+        return None;
+    }
+
+    let t = f * dot(edge2, q);
+    if t > EPSILON {
+        Some((t, [u, w]))
+    } else {
+        None
+    }
+}
+
+// testBox() in shaders/lib/traversal.glsl, ignoring boxes behind max_dist
+fn hits_box(o: Vec3, dir_inv: Vec3, aabb: &[f32; 6], max_dist: f32) -> bool {
+    let mut tmin = f32::NEG_INFINITY;
+    let mut tmax = f32::INFINITY;
+    for axis in 0..3 {
+        let t1 = (aabb[2 * axis] - o[axis]) * dir_inv[axis];
+        let t2 = (aabb[2 * axis + 1] - o[axis]) * dir_inv[axis];
+        tmin = tmin.max(t1.min(t2));
+        tmax = tmax.min(t1.max(t2));
+    }
+    tmax >= tmin.max(0.0) && tmin < max_dist
+}
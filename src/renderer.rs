@@ -0,0 +1,817 @@
+use std::sync::Arc;
+
+use glsl_to_spirv::ShaderType;
+use image::RgbaImage;
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer, ImmutableBuffer};
+use vulkano::command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet};
+use vulkano::device::{Device, Queue};
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass};
+use vulkano::image::{AttachmentImage, Dimensions, ImageUsage, ImmutableImage, StorageImage};
+use vulkano::pipeline::shader::ShaderModule;
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::{
+    ComputePipeline, ComputePipelineAbstract, GraphicsPipeline, GraphicsPipelineAbstract,
+};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use vulkano::sync::GpuFuture;
+
+use camera::Camera;
+use hot_reload;
+use light::Light;
+use material::Material;
+use object::bvh::Node;
+use post::{Effect, PostChain};
+use quad::{self, Vertex};
+use scene::Scene;
+use shaders;
+use tiles::TileScheduler;
+use tonemap::ToneMapping;
+
+pub use shaders::WorkgroupSize;
+
+// Maximum number of reflections and refractions along a ray, unless the
+// settings give another one
+pub const DEFAULT_MAX_DEPTH: u32 = 4;
+
+// The raytracer renders into a floating point image, which is then post
+// processed and tone mapped onto the output image
+const HDR_FORMAT: Format = Format::R16G16B16A16Sfloat;
+
+// Format of the images returned by screenshot()
+const SCREENSHOT_FORMAT: Format = Format::R8G8B8A8Unorm;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct PushData {
+    camera_position: [f32; 4],
+    camera_direction: [f32; 4],
+    time: f32,
+    resolution: [u32; 2],
+    // Number of frames accumulated since the image last changed
+    frame: u32,
+    path_tracing: u32,
+    max_depth: u32,
+    // Rotation of the environment map around the Y axis in radians
+    environment_rotation: f32,
+    // The tile traced by the compute shader
+    tile_offset: [u32; 2],
+    tile_size: [u32; 2],
+}
+
+/**
+ * Settings that are fixed when the renderer is created
+ */
+#[derive(Clone)]
+pub struct Settings {
+    pub post: Vec<Effect>,
+    // Trace in the compute shader instead of the fragment shader
+    pub compute: bool,
+    pub workgroup_size: WorkgroupSize,
+    // Edge length of the tiles a frame is traced in, 0 traces it at once
+    pub tile_size: u32,
+    pub tiles_per_submit: usize,
+    pub tone_mapping: ToneMapping,
+    pub environment_rotation: f32,
+    // Maximum number of reflections and refractions along a ray
+    pub max_depth: u32,
+}
+
+// The scene as uploaded to the GPU
+struct SceneBuffers {
+    vertices: Arc<ImmutableBuffer<[[f32; 4]]>>,
+    indices: Arc<ImmutableBuffer<[[u32; 4]]>>,
+    bvh: Arc<ImmutableBuffer<[Node]>>,
+    lights: Arc<ImmutableBuffer<[Light]>>,
+    environment: Arc<ImmutableImage<Format>>,
+    environment_distribution: Arc<ImmutableBuffer<[f32]>>,
+    materials: Arc<ImmutableBuffer<[Material]>>,
+    uvs: Arc<ImmutableBuffer<[[f32; 2]]>>,
+    textures: Arc<ImmutableImage<Format>>,
+    normals: Arc<ImmutableBuffer<[[f32; 4]]>>,
+}
+
+// Everything that depends on the size of the image
+struct Targets {
+    set: Arc<DescriptorSet + Send + Sync>,
+    compute_set: Arc<DescriptorSet + Send + Sync>,
+    scene_framebuffer: Arc<FramebufferAbstract + Send + Sync>,
+    tonemap_set: Arc<DescriptorSet + Send + Sync>,
+}
+
+/**
+ * Traces a scene into an HDR image, runs the post-processing chain over it and
+ * tone maps the result into a framebuffer of the output render pass
+ */
+pub struct Renderer {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    quad: Arc<CpuAccessibleBuffer<[Vertex]>>,
+
+    render_pass: Arc<RenderPassAbstract + Send + Sync>,
+    pipeline: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    compute_pipeline: Arc<ComputePipelineAbstract + Send + Sync>,
+    workgroup_size: WorkgroupSize,
+    use_compute: bool,
+
+    post_chain: PostChain,
+    output_pass: Arc<RenderPassAbstract + Send + Sync>,
+    tonemap_pipeline: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    screenshot_pass: Arc<RenderPassAbstract + Send + Sync>,
+    screenshot_pipeline: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    pub tone_mapping: ToneMapping,
+
+    hdr_sampler: Arc<Sampler>,
+    environment_sampler: Arc<Sampler>,
+    texture_sampler: Arc<Sampler>,
+
+    scene: Option<SceneBuffers>,
+    targets: Option<Targets>,
+    dimensions: [u32; 2],
+
+    push_data: PushData,
+    camera: Option<Camera>,
+    // Restarts accumulation of path traced samples on the next frame
+    reset_accumulation: bool,
+    tiles: TileScheduler,
+    paused: bool,
+}
+
+fn build_tracer_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<RenderPassAbstract + Send + Sync>,
+    fs: &ShaderModule,
+) -> Result<Arc<GraphicsPipelineAbstract + Send + Sync>, String> {
+    let vs = shaders::get_vertex_shader(device.clone());
+    GraphicsPipeline::start()
+        .vertex_input_single_buffer::<Vertex>()
+        .vertex_shader(vs.main_entry_point(), ())
+        .triangle_list()
+        .viewports_dynamic_scissors_irrelevant(1)
+        .fragment_shader(shaders::fragment_entry_point(fs), ())
+        .render_pass(Subpass::from(render_pass, 0).unwrap())
+        .build(device)
+        .map(|p| Arc::new(p) as Arc<GraphicsPipelineAbstract + Send + Sync>)
+        .map_err(|e| e.to_string())
+}
+
+// Traces into the HDR image as a storage image, the fragment shader pipeline
+// is kept to compare against
+fn build_compute_pipeline(
+    device: Arc<Device>,
+    cs: &ShaderModule,
+    workgroup_size: &WorkgroupSize,
+) -> Result<Arc<ComputePipelineAbstract + Send + Sync>, String> {
+    ComputePipeline::new(device, &shaders::compute_entry_point(cs), workgroup_size)
+        .map(|p| Arc::new(p) as Arc<ComputePipelineAbstract + Send + Sync>)
+        .map_err(|e| e.to_string())
+}
+
+fn build_tonemap_pipeline(
+    device: Arc<Device>,
+    format: Format,
+) -> (
+    Arc<RenderPassAbstract + Send + Sync>,
+    Arc<GraphicsPipelineAbstract + Send + Sync>,
+) {
+    let render_pass = Arc::new(
+        single_pass_renderpass!(device.clone(),
+        attachments: {
+            color: {
+                load: DontCare,
+                store: Store,
+                format: format,
+                samples: 1,
+            }
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {}
+        }
+    ).unwrap(),
+    ) as Arc<RenderPassAbstract + Send + Sync>;
+
+    let vs = shaders::get_vertex_shader(device.clone());
+    let fs = shaders::get_tonemap_shader(device.clone());
+    let pipeline = Arc::new(
+        GraphicsPipeline::start()
+            .vertex_input_single_buffer::<Vertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(device)
+            .unwrap(),
+    ) as Arc<GraphicsPipelineAbstract + Send + Sync>;
+
+    (render_pass, pipeline)
+}
+
+fn viewport(offset: [u32; 2], size: [u32; 2]) -> DynamicState {
+    DynamicState {
+        line_width: None,
+        viewports: Some(vec![Viewport {
+            origin: [offset[0] as f32, offset[1] as f32],
+            dimensions: [size[0] as f32, size[1] as f32],
+            depth_range: 0.0..1.0,
+        }]),
+        scissors: None,
+    }
+}
+
+fn storage_buffer<T, I>(data: I, queue: Arc<Queue>) -> (Arc<ImmutableBuffer<[T]>>, Box<GpuFuture>)
+where
+    T: Send + Sync + 'static,
+    I: ExactSizeIterator<Item = T>,
+{
+    let (buffer, future) = ImmutableBuffer::from_iter(
+        data,
+        BufferUsage {
+            storage_buffer: true,
+            ..BufferUsage::none()
+        },
+        queue,
+    ).expect("Failed to create storage buffer");
+    (buffer, Box::new(future))
+}
+
+// The graphics and compute pipelines share bindings 0 to 10
+macro_rules! scene_set {
+    ($pipeline:expr, $scene:expr, $accumulation:expr, $renderer:expr) => {
+        PersistentDescriptorSet::start($pipeline, 0)
+            .add_buffer($scene.vertices.clone())
+            .unwrap()
+            .add_buffer($scene.indices.clone())
+            .unwrap()
+            .add_buffer($scene.bvh.clone())
+            .unwrap()
+            .add_buffer($scene.lights.clone())
+            .unwrap()
+            .add_image($accumulation)
+            .unwrap()
+            .add_sampled_image($scene.environment.clone(), $renderer.environment_sampler.clone())
+            .unwrap()
+            .add_buffer($scene.environment_distribution.clone())
+            .unwrap()
+            .add_buffer($scene.materials.clone())
+            .unwrap()
+            .add_buffer($scene.uvs.clone())
+            .unwrap()
+            .add_sampled_image($scene.textures.clone(), $renderer.texture_sampler.clone())
+            .unwrap()
+            .add_buffer($scene.normals.clone())
+            .unwrap()
+    };
+}
+
+impl Renderer {
+    /**
+     * Creates a renderer that draws into images of `output_format`. The
+     * returned future finishes uploading the post-processing resources.
+     */
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        output_format: Format,
+        dimensions: [u32; 2],
+        settings: Settings,
+    ) -> (Renderer, Box<GpuFuture>) {
+        let render_pass = Arc::new(
+            single_pass_renderpass!(device.clone(),
+            attachments: {
+                color: {
+                    // Tiles only overwrite part of the image
+                    load: Load,
+                    store: Store,
+                    format: HDR_FORMAT,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {}
+            }
+        ).unwrap(),
+        ) as Arc<RenderPassAbstract + Send + Sync>;
+
+        let fs = shaders::get_fragment_shader(device.clone());
+        let pipeline = build_tracer_pipeline(device.clone(), render_pass.clone(), fs.module())
+            .expect("Failed to create pipeline");
+
+        let cs = shaders::get_compute_shader(device.clone());
+        let compute_pipeline =
+            build_compute_pipeline(device.clone(), cs.module(), &settings.workgroup_size)
+                .expect("Failed to create compute pipeline");
+
+        let (output_pass, tonemap_pipeline) = build_tonemap_pipeline(device.clone(), output_format);
+        let (screenshot_pass, screenshot_pipeline) =
+            build_tonemap_pipeline(device.clone(), SCREENSHOT_FORMAT);
+
+        let (post_chain, future) =
+            PostChain::new(device.clone(), queue.clone(), HDR_FORMAT, settings.post);
+
+        // Wraps around horizontally, but not over the poles
+        let environment_sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            SamplerAddressMode::Repeat,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        ).expect("Failed to create environment sampler");
+
+        let renderer = Renderer {
+            quad: quad::fullscreen_quad(device.clone()),
+            render_pass,
+            pipeline,
+            compute_pipeline,
+            workgroup_size: settings.workgroup_size,
+            use_compute: settings.compute,
+            post_chain,
+            output_pass,
+            tonemap_pipeline,
+            screenshot_pass,
+            screenshot_pipeline,
+            tone_mapping: settings.tone_mapping,
+            hdr_sampler: Sampler::simple_repeat_linear_no_mipmap(device.clone()),
+            environment_sampler,
+            texture_sampler: Sampler::simple_repeat_linear_no_mipmap(device.clone()),
+            scene: None,
+            targets: None,
+            dimensions,
+            push_data: PushData {
+                camera_position: [0.0; 4],
+                camera_direction: [0.0; 4],
+                time: 0.0,
+                resolution: dimensions,
+                frame: 0,
+                path_tracing: 0,
+                max_depth: settings.max_depth,
+                environment_rotation: settings.environment_rotation,
+                tile_offset: [0, 0],
+                tile_size: dimensions,
+            },
+            camera: None,
+            reset_accumulation: true,
+            tiles: TileScheduler::new(settings.tile_size, settings.tiles_per_submit),
+            paused: false,
+            device,
+            queue,
+        };
+
+        (renderer, future)
+    }
+
+    /**
+     * The render pass the framebuffers given to render_frame() have to use
+     */
+    pub fn output_pass(&self) -> Arc<RenderPassAbstract + Send + Sync> {
+        self.output_pass.clone()
+    }
+
+    /**
+     * Uploads a scene, replacing the previous one. Rendering has to wait for
+     * the returned future.
+     */
+    pub fn load_scene(&mut self, scene: Scene) -> Box<GpuFuture> {
+        let queue = self.queue.clone();
+        let Scene {
+            object,
+            materials,
+            lights,
+            environment,
+            textures,
+        } = scene;
+
+        let (vertices, f1) = storage_buffer(object.vertices.into_iter(), queue.clone());
+        let (indices, f2) = storage_buffer(object.indices.into_iter(), queue.clone());
+        let (bvh, f3) = storage_buffer(object.bvh.into_iter(), queue.clone());
+        let (lights, f4) = storage_buffer(lights.into_iter(), queue.clone());
+
+        let (environment_image, f5) = ImmutableImage::from_iter(
+            environment.pixels.iter().cloned(),
+            Dimensions::Dim2d {
+                width: environment.width,
+                height: environment.height,
+            },
+            Format::R32G32B32A32Sfloat,
+            queue.clone(),
+        ).expect("Failed to create environment texture");
+
+        let (environment_distribution, f6) =
+            storage_buffer(environment.distribution().into_iter(), queue.clone());
+        let (materials, f7) = storage_buffer(materials.into_iter(), queue.clone());
+        let (uvs, f8) = storage_buffer(object.uvs.into_iter(), queue.clone());
+        let (normals, f10) = storage_buffer(object.normals.into_iter(), queue.clone());
+
+        let (textures, f9) = ImmutableImage::from_iter(
+            textures.pixels.into_iter(),
+            Dimensions::Dim2dArray {
+                width: textures.width,
+                height: textures.height,
+                array_layers: textures.layers,
+            },
+            Format::R8G8B8A8Unorm,
+            queue,
+        ).expect("Failed to create texture array");
+
+        self.scene = Some(SceneBuffers {
+            vertices,
+            indices,
+            bvh,
+            lights,
+            environment: environment_image,
+            environment_distribution,
+            materials,
+            uvs,
+            textures,
+            normals,
+        });
+        self.create_targets();
+
+        Box::new(
+            f1.join(f2)
+                .join(f3)
+                .join(f4)
+                .join(f5)
+                .join(f6)
+                .join(f7)
+                .join(f8)
+                .join(f9)
+                .join(f10),
+        )
+    }
+
+    /**
+     * Resizes the images rendered into, which restarts accumulation
+     */
+    pub fn resize(&mut self, dimensions: [u32; 2]) {
+        self.dimensions = dimensions;
+        self.push_data.resolution = dimensions;
+        self.create_targets();
+    }
+
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.dimensions
+    }
+
+    fn create_targets(&mut self) {
+        self.targets = None;
+        self.reset();
+        let scene = match self.scene {
+            Some(ref scene) => scene,
+            None => return,
+        };
+        let dimensions = self.dimensions;
+
+        let accumulation = StorageImage::new(
+            self.device.clone(),
+            Dimensions::Dim2d {
+                width: dimensions[0],
+                height: dimensions[1],
+            },
+            Format::R32G32B32A32Sfloat,
+            Some(self.queue.family()),
+        ).expect("Failed to create accumulation image");
+
+        let set = Arc::new(
+            scene_set!(self.pipeline.clone(), scene, accumulation.clone(), self)
+                .build()
+                .unwrap(),
+        );
+
+        let hdr_image = AttachmentImage::with_usage(
+            self.device.clone(),
+            dimensions,
+            HDR_FORMAT,
+            ImageUsage {
+                color_attachment: true,
+                storage: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+        ).expect("Failed to create HDR image");
+
+        // Same bindings as the graphics pipeline, plus the image to write to
+        let compute_set = Arc::new(
+            scene_set!(self.compute_pipeline.clone(), scene, accumulation, self)
+                .add_image(hdr_image.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let scene_framebuffer = Arc::new(
+            Framebuffer::start(self.render_pass.clone())
+                .add(hdr_image.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        self.post_chain.resize(hdr_image, dimensions);
+
+        let tonemap_set = Arc::new(
+            PersistentDescriptorSet::start(self.tonemap_pipeline.clone(), 0)
+                .add_sampled_image(self.post_chain.output(), self.hdr_sampler.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        self.targets = Some(Targets {
+            set,
+            compute_set,
+            scene_framebuffer,
+            tonemap_set,
+        });
+    }
+
+    /**
+     * Recompiles the tracer shaders from disk. A shader that fails to compile
+     * keeps the last working pipeline.
+     */
+    pub fn reload_shaders(&mut self) {
+        let path = "shaders/fragment.glsl";
+        match hot_reload::compile(self.device.clone(), path, ShaderType::Fragment).and_then(
+            |module| build_tracer_pipeline(self.device.clone(), self.render_pass.clone(), &module),
+        ) {
+            Ok(pipeline) => {
+                self.pipeline = pipeline;
+                println!("Reloaded {}", path);
+            }
+            Err(e) => println!("Failed to reload {}:\n{}", path, e),
+        }
+
+        let path = "shaders/compute.glsl";
+        match hot_reload::compile(self.device.clone(), path, ShaderType::Compute).and_then(
+            |module| build_compute_pipeline(self.device.clone(), &module, &self.workgroup_size),
+        ) {
+            Ok(pipeline) => {
+                self.compute_pipeline = pipeline;
+                println!("Reloaded {}", path);
+            }
+            Err(e) => println!("Failed to reload {}:\n{}", path, e),
+        }
+
+        // The descriptor sets belong to the old pipelines
+        self.create_targets();
+    }
+
+    /**
+     * Restarts accumulation and drops the rest of the current frame
+     */
+    pub fn reset(&mut self) {
+        self.reset_accumulation = true;
+        self.tiles.cancel();
+    }
+
+    /**
+     * Whether all tiles of the current frame were submitted, so the next one
+     * can be started
+     */
+    pub fn needs_frame(&self) -> bool {
+        self.tiles.is_done() && !self.paused
+    }
+
+    /**
+     * Starts tracing a new frame. Time, camera and accumulation only advance
+     * between frames, so all tiles of a frame show the same scene.
+     */
+    pub fn start_frame(&mut self, camera: Camera, time: f32) {
+        if self.camera != Some(camera) {
+            self.camera = Some(camera);
+            self.reset_accumulation = true;
+        }
+        self.push_data.time = time;
+        let [x, y, z] = camera.position;
+        self.push_data.camera_position = [x, y, z, 0.0];
+        let [x, y, z] = camera.direction;
+        self.push_data.camera_direction = [x, y, z, 0.0];
+
+        if self.reset_accumulation {
+            self.push_data.frame = 0;
+            self.reset_accumulation = false;
+        } else {
+            self.push_data.frame += 1;
+        }
+
+        self.tiles.start(self.dimensions);
+    }
+
+    /**
+     * Records the next tiles of the current frame, the post-processing chain
+     * and tone mapping into `framebuffer`
+     */
+    pub fn render_frame(
+        &mut self,
+        framebuffer: Arc<FramebufferAbstract + Send + Sync>,
+    ) -> AutoCommandBuffer {
+        let mut builder =
+            AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.queue.family())
+                .unwrap();
+
+        builder = self.trace(builder);
+        builder = self.post_chain.draw(builder);
+
+        let targets = self.targets.as_ref().expect("No scene loaded");
+        builder
+            .begin_render_pass(framebuffer, false, vec![ClearValue::None])
+            .unwrap()
+            .draw(
+                self.tonemap_pipeline.clone(),
+                &viewport([0, 0], self.dimensions),
+                vec![self.quad.clone() as Arc<BufferAccess + Send + Sync>],
+                targets.tonemap_set.clone(),
+                self.tone_mapping.push_data(),
+            ).unwrap()
+            .end_render_pass()
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    fn trace(&mut self, mut builder: AutoCommandBufferBuilder) -> AutoCommandBufferBuilder {
+        let targets = self.targets.as_ref().expect("No scene loaded");
+        let next_tiles = self.tiles.next_tiles();
+
+        if self.use_compute {
+            let size = self.workgroup_size;
+            for tile in &next_tiles {
+                let groups = [
+                    (tile.size[0] + size.width - 1) / size.width,
+                    (tile.size[1] + size.height - 1) / size.height,
+                    1,
+                ];
+                builder = builder
+                    .dispatch(
+                        groups,
+                        self.compute_pipeline.clone(),
+                        targets.compute_set.clone(),
+                        PushData {
+                            tile_offset: tile.offset,
+                            tile_size: tile.size,
+                            ..self.push_data
+                        },
+                    ).unwrap();
+            }
+        } else if !next_tiles.is_empty() {
+            builder = builder
+                .begin_render_pass(
+                    targets.scene_framebuffer.clone(),
+                    false,
+                    vec![ClearValue::None],
+                ).unwrap();
+            // gl_FragCoord stays relative to the whole image, so the viewport
+            // only restricts which pixels are traced
+            for tile in &next_tiles {
+                builder = builder
+                    .draw(
+                        self.pipeline.clone(),
+                        &viewport(tile.offset, tile.size),
+                        vec![self.quad.clone() as Arc<BufferAccess + Send + Sync>],
+                        targets.set.clone(),
+                        self.push_data,
+                    ).unwrap();
+            }
+            builder = builder.end_render_pass().unwrap();
+        }
+        builder
+    }
+
+    /**
+     * Tone maps the current image into an 8 bit RGBA image and reads it back.
+     * Waits for `after`, usually the end of the previous frame, and the copy
+     * to finish.
+     */
+    pub fn screenshot<F: GpuFuture>(&self, after: F) -> RgbaImage {
+        let targets = self.targets.as_ref().expect("No scene loaded");
+        let [width, height] = self.dimensions;
+
+        let image = AttachmentImage::with_usage(
+            self.device.clone(),
+            self.dimensions,
+            SCREENSHOT_FORMAT,
+            ImageUsage {
+                color_attachment: true,
+                transfer_source: true,
+                ..ImageUsage::none()
+            },
+        ).expect("Failed to create screenshot image");
+
+        let framebuffer = Arc::new(
+            Framebuffer::start(self.screenshot_pass.clone())
+                .add(image.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let buffer = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_destination(),
+            (0..width * height * 4).map(|_| 0u8),
+        ).expect("Failed to create screenshot buffer");
+
+        let command_buffer =
+            AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.queue.family())
+                .unwrap()
+                .begin_render_pass(framebuffer, false, vec![ClearValue::None])
+                .unwrap()
+                .draw(
+                    self.screenshot_pipeline.clone(),
+                    &viewport([0, 0], self.dimensions),
+                    vec![self.quad.clone() as Arc<BufferAccess + Send + Sync>],
+                    targets.tonemap_set.clone(),
+                    self.tone_mapping.push_data(),
+                ).unwrap()
+                .end_render_pass()
+                .unwrap()
+                .copy_image_to_buffer(image, buffer.clone())
+                .unwrap()
+                .build()
+                .unwrap();
+
+        after
+            .then_execute(self.queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .expect("Failed to take screenshot")
+            .wait(None)
+            .unwrap();
+
+        let pixels = buffer.read().unwrap().to_vec();
+        RgbaImage::from_raw(width, height, pixels).unwrap()
+    }
+
+    pub fn set_path_tracing(&mut self, path_tracing: bool) {
+        self.push_data.path_tracing = path_tracing as u32;
+        self.reset();
+    }
+
+    pub fn path_tracing(&self) -> bool {
+        self.push_data.path_tracing != 0
+    }
+
+    /**
+     * Switches between tracing in the compute and the fragment shader
+     */
+    pub fn set_compute(&mut self, compute: bool) {
+        self.use_compute = compute;
+    }
+
+    pub fn compute(&self) -> bool {
+        self.use_compute
+    }
+
+    /**
+     * Changes the maximum number of reflections and refractions along a ray,
+     * which restarts accumulation
+     */
+    pub fn set_max_depth(&mut self, max_depth: u32) {
+        self.push_data.max_depth = max_depth;
+        self.reset();
+    }
+
+    pub fn max_depth(&self) -> u32 {
+        self.push_data.max_depth
+    }
+
+    pub fn rotate_environment(&mut self, angle: f32) {
+        self.push_data.environment_rotation += angle;
+        self.reset();
+    }
+
+    /**
+     * A paused renderer stops tracing, the last image stays visible
+     */
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.tiles.cancel();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /**
+     * Fraction of the current frame that has been submitted, if a frame
+     * takes more than one submission
+     */
+    pub fn progress(&self) -> Option<f32> {
+        if self.tiles.is_tiled() {
+            Some(self.tiles.progress())
+        } else {
+            None
+        }
+    }
+}
+
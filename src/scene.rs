@@ -0,0 +1,16 @@
+use environment::EnvironmentMap;
+use light::Light;
+use material::Material;
+use object::Object;
+use texture::TextureArray;
+
+/**
+ * Everything the tracer needs to know about the scene
+ */
+pub struct Scene {
+    pub object: Object,
+    pub materials: Vec<Material>,
+    pub lights: Vec<Light>,
+    pub environment: EnvironmentMap,
+    pub textures: TextureArray,
+}
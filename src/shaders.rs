@@ -1,5 +1,14 @@
+// The shaders are loaded from target/shaders, where build.rs puts them with
+// their includes resolved. Cargo then also knows to rebuild when they change.
+
+use std::ffi::CStr;
 use std::sync::Arc;
+use vulkano::descriptor::descriptor::ShaderStages;
 use vulkano::device::Device;
+use vulkano::pipeline::shader::{
+    ComputeEntryPoint, GraphicsEntryPoint, GraphicsShaderType, ShaderModule,
+    SpecializationConstants, SpecializationMapEntry,
+};
 
 mod vs {
     #[derive(VulkanoShader)]
@@ -17,13 +26,111 @@ void main() {
     struct Dummy;
 }
 
-#[allow(dead_code)] // Used to force recompilation of shader change
-const X: &str = include_str!("../shaders/fragment.glsl");
-
 mod fs {
     #[derive(VulkanoShader)]
     #[ty = "fragment"]
-    #[path = "shaders/fragment.glsl"]
+    #[path = "target/shaders/fragment.glsl"]
+    #[allow(dead_code)]
+    struct Dummy;
+}
+
+mod cs {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "target/shaders/compute.glsl"]
+    #[allow(dead_code)]
+    struct Dummy;
+}
+
+/**
+ * Size of the workgroups of the compute shader, bound to local_size_x_id and
+ * local_size_y_id
+ */
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct WorkgroupSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+unsafe impl SpecializationConstants for WorkgroupSize {
+    fn descriptors() -> &'static [SpecializationMapEntry] {
+        static DESCRIPTORS: [SpecializationMapEntry; 2] = [
+            SpecializationMapEntry {
+                constant_id: 0,
+                offset: 0,
+                size: 4,
+            },
+            SpecializationMapEntry {
+                constant_id: 1,
+                offset: 4,
+                size: 4,
+            },
+        ];
+        &DESCRIPTORS
+    }
+}
+
+mod tonemap_fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[path = "target/shaders/tonemap.glsl"]
+    #[allow(dead_code)]
+    struct Dummy;
+}
+
+mod bloom_downsample_fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[path = "target/shaders/post/bloom_downsample.glsl"]
+    #[allow(dead_code)]
+    struct Dummy;
+}
+
+mod bloom_upsample_fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[path = "target/shaders/post/bloom_upsample.glsl"]
+    #[allow(dead_code)]
+    struct Dummy;
+}
+
+mod bloom_composite_fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[path = "target/shaders/post/bloom_composite.glsl"]
+    #[allow(dead_code)]
+    struct Dummy;
+}
+
+mod vignette_fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[path = "target/shaders/post/vignette.glsl"]
+    #[allow(dead_code)]
+    struct Dummy;
+}
+
+mod chromatic_aberration_fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[path = "target/shaders/post/chromatic_aberration.glsl"]
+    #[allow(dead_code)]
+    struct Dummy;
+}
+
+mod fxaa_fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[path = "target/shaders/post/fxaa.glsl"]
+    #[allow(dead_code)]
+    struct Dummy;
+}
+
+mod color_grading_fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[path = "target/shaders/post/color_grading.glsl"]
     #[allow(dead_code)]
     struct Dummy;
 }
@@ -32,6 +139,81 @@ pub fn get_fragment_shader(device: Arc<Device>) -> fs::Shader {
     fs::Shader::load(device).expect("failed to create shader module")
 }
 
+pub fn get_compute_shader(device: Arc<Device>) -> cs::Shader {
+    cs::Shader::load(device).expect("failed to create shader module")
+}
+
+/**
+ * The entry point of a compute shader module built from compute.glsl, taking
+ * the workgroup size as specialization constants. The derived entry point
+ * does not know about them, because glslang leaves the local size ids unnamed.
+ */
+pub fn compute_entry_point(module: &ShaderModule) -> ComputeEntryPoint<WorkgroupSize, cs::Layout> {
+    unsafe {
+        module.compute_entry_point(
+            CStr::from_bytes_with_nul_unchecked(b"main\0"),
+            cs::Layout(ShaderStages {
+                compute: true,
+                ..ShaderStages::none()
+            }),
+        )
+    }
+}
+
+/**
+ * The entry point of a fragment shader module built from fragment.glsl. The
+ * interface is taken from the version compiled into the binary, so a reloaded
+ * shader has to keep the same inputs, outputs and bindings.
+ */
+pub fn fragment_entry_point(
+    module: &ShaderModule,
+) -> GraphicsEntryPoint<(), fs::MainInput, fs::MainOutput, fs::Layout> {
+    unsafe {
+        module.graphics_entry_point(
+            CStr::from_bytes_with_nul_unchecked(b"main\0"),
+            fs::MainInput,
+            fs::MainOutput,
+            fs::Layout(ShaderStages {
+                fragment: true,
+                ..ShaderStages::none()
+            }),
+            GraphicsShaderType::Fragment,
+        )
+    }
+}
+
+pub fn get_tonemap_shader(device: Arc<Device>) -> tonemap_fs::Shader {
+    tonemap_fs::Shader::load(device).expect("failed to create shader module")
+}
+
+pub fn get_bloom_downsample_shader(device: Arc<Device>) -> bloom_downsample_fs::Shader {
+    bloom_downsample_fs::Shader::load(device).expect("failed to create shader module")
+}
+
+pub fn get_bloom_upsample_shader(device: Arc<Device>) -> bloom_upsample_fs::Shader {
+    bloom_upsample_fs::Shader::load(device).expect("failed to create shader module")
+}
+
+pub fn get_bloom_composite_shader(device: Arc<Device>) -> bloom_composite_fs::Shader {
+    bloom_composite_fs::Shader::load(device).expect("failed to create shader module")
+}
+
+pub fn get_vignette_shader(device: Arc<Device>) -> vignette_fs::Shader {
+    vignette_fs::Shader::load(device).expect("failed to create shader module")
+}
+
+pub fn get_chromatic_aberration_shader(device: Arc<Device>) -> chromatic_aberration_fs::Shader {
+    chromatic_aberration_fs::Shader::load(device).expect("failed to create shader module")
+}
+
+pub fn get_fxaa_shader(device: Arc<Device>) -> fxaa_fs::Shader {
+    fxaa_fs::Shader::load(device).expect("failed to create shader module")
+}
+
+pub fn get_color_grading_shader(device: Arc<Device>) -> color_grading_fs::Shader {
+    color_grading_fs::Shader::load(device).expect("failed to create shader module")
+}
+
 pub fn get_vertex_shader(device: Arc<Device>) -> vs::Shader {
     vs::Shader::load(device).expect("failed to create shader module")
 }
@@ -0,0 +1,52 @@
+use image::{self, imageops, FilterType, ImageResult};
+
+/**
+ * All textures of the scene in RGBA, scaled to the same size so they fit into
+ * the layers of a single array image
+ */
+pub struct TextureArray {
+    pub width: u32,
+    pub height: u32,
+    pub layers: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+/**
+ * Loads PNG or JPEG files into consecutive layers. Without any files a single
+ * white layer is created, as the shader always needs an image bound.
+ */
+pub fn load_textures(paths: &[String]) -> ImageResult<TextureArray> {
+    if paths.is_empty() {
+        return Ok(TextureArray {
+            width: 1,
+            height: 1,
+            layers: 1,
+            pixels: vec![[255; 4]],
+        });
+    }
+
+    let mut images = Vec::with_capacity(paths.len());
+    for path in paths {
+        images.push(image::open(path)?.to_rgba());
+    }
+
+    let width = images.iter().map(|i| i.width()).max().unwrap();
+    let height = images.iter().map(|i| i.height()).max().unwrap();
+
+    let mut pixels = Vec::with_capacity((width * height) as usize * images.len());
+    for img in images {
+        let img = if img.dimensions() == (width, height) {
+            img
+        } else {
+            imageops::resize(&img, width, height, FilterType::Triangle)
+        };
+        pixels.extend(img.pixels().map(|p| p.data));
+    }
+
+    Ok(TextureArray {
+        width,
+        height,
+        layers: paths.len() as u32,
+        pixels,
+    })
+}
@@ -0,0 +1,94 @@
+/**
+ * A rectangle of the image that is traced in one dispatch
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct Tile {
+    pub offset: [u32; 2],
+    pub size: [u32; 2],
+}
+
+/**
+ * Splits a frame into tiles that are traced over several submissions. On big
+ * scenes a single dispatch over the whole image can run long enough to trip
+ * the driver watchdog, while the window stays responsive between submissions.
+ */
+pub struct TileScheduler {
+    // Edge length of the tiles in pixels, 0 traces the whole image at once
+    tile_size: u32,
+    tiles_per_submit: usize,
+    tiles: Vec<Tile>,
+    next: usize,
+}
+
+impl TileScheduler {
+    pub fn new(tile_size: u32, tiles_per_submit: usize) -> TileScheduler {
+        TileScheduler {
+            tile_size,
+            tiles_per_submit: tiles_per_submit.max(1),
+            tiles: vec![],
+            next: 0,
+        }
+    }
+
+    /**
+     * Starts a new frame, row by row from the top left
+     */
+    pub fn start(&mut self, dimensions: [u32; 2]) {
+        let [width, height] = dimensions;
+        let size = if self.tile_size == 0 {
+            width.max(height)
+        } else {
+            self.tile_size
+        };
+
+        self.tiles.clear();
+        for y in (0..height).step_by(size as usize) {
+            for x in (0..width).step_by(size as usize) {
+                self.tiles.push(Tile {
+                    offset: [x, y],
+                    size: [size.min(width - x), size.min(height - y)],
+                });
+            }
+        }
+        self.next = 0;
+    }
+
+    /**
+     * The tiles to trace in the next submission
+     */
+    pub fn next_tiles(&mut self) -> Vec<Tile> {
+        let end = (self.next + self.tiles_per_submit).min(self.tiles.len());
+        let tiles = self.tiles[self.next..end].to_vec();
+        self.next = end;
+        tiles
+    }
+
+    /**
+     * Drops the remaining tiles of the current frame
+     */
+    pub fn cancel(&mut self) {
+        self.next = self.tiles.len();
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.tiles.len()
+    }
+
+    /**
+     * Whether a frame takes more than one submission
+     */
+    pub fn is_tiled(&self) -> bool {
+        self.tiles.len() > self.tiles_per_submit
+    }
+
+    /**
+     * Fraction of the current frame that has been submitted
+     */
+    pub fn progress(&self) -> f32 {
+        if self.tiles.is_empty() {
+            1.0
+        } else {
+            self.next as f32 / self.tiles.len() as f32
+        }
+    }
+}
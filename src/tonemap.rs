@@ -0,0 +1,90 @@
+/**
+ * Curves mapping the unbounded HDR image to displayable colors
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    Reinhard,
+    // Krzysztof Narkowicz's fit of the ACES filmic curve
+    Aces,
+    // John Hable's filmic curve from Uncharted 2
+    Uncharted2,
+}
+
+impl Operator {
+    pub fn next(self) -> Operator {
+        match self {
+            Operator::Reinhard => Operator::Aces,
+            Operator::Aces => Operator::Uncharted2,
+            Operator::Uncharted2 => Operator::Reinhard,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Operator> {
+        match name {
+            "reinhard" => Some(Operator::Reinhard),
+            "aces" => Some(Operator::Aces),
+            "uncharted2" => Some(Operator::Uncharted2),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Settings of the tone mapping pass
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct ToneMapping {
+    pub operator: Operator,
+    // Exposure in stops, the image is scaled by 2^exposure
+    pub exposure: f32,
+    pub gamma: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+pub struct PushData {
+    operator: u32,
+    exposure: f32,
+    gamma: f32,
+}
+
+impl ToneMapping {
+    pub fn push_data(&self) -> PushData {
+        PushData {
+            operator: self.operator as u32,
+            exposure: self.exposure.exp2(),
+            gamma: self.gamma,
+        }
+    }
+
+    /**
+     * Maps a linear HDR color to display values between 0 and 1, like the
+     * tone mapping shader
+     */
+    pub fn apply(&self, color: [f32; 3]) -> [f32; 3] {
+        let exposure = self.exposure.exp2();
+        let map = |c: f32| {
+            let c = c * exposure;
+            let c = match self.operator {
+                Operator::Reinhard => c / (1.0 + c),
+                Operator::Aces => ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14))
+                    .max(0.0)
+                    .min(1.0),
+                Operator::Uncharted2 => hable_curve(c * 2.0) / hable_curve(11.2),
+            };
+            c.max(0.0).min(1.0).powf(1.0 / self.gamma)
+        };
+        [map(color[0]), map(color[1]), map(color[2])]
+    }
+}
+
+fn hable_curve(x: f32) -> f32 {
+    const A: f32 = 0.15; // shoulder strength
+    const B: f32 = 0.50; // linear strength
+    const C: f32 = 0.10; // linear angle
+    const D: f32 = 0.20; // toe strength
+    const E: f32 = 0.02; // toe numerator
+    const F: f32 = 0.30; // toe denominator
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
@@ -0,0 +1,169 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use coding_assignment::camera::Camera;
+use coding_assignment::environment::EnvironmentMap;
+use coding_assignment::light::Light;
+use coding_assignment::material::Material;
+use coding_assignment::object;
+use coding_assignment::texture;
+use coding_assignment::tonemap::{Operator, ToneMapping};
+use coding_assignment::Scene;
+use image::{Rgb, RgbImage};
+
+// Small enough to render quickly on a software driver or the CPU
+pub const SIZE: [u32; 2] = [160, 120];
+
+// Root mean square error over all channels, in 8 bit steps
+const MAX_RMSE: f64 = 3.0;
+// A pixel differs if one of its channels is off by more than this
+const PIXEL_TOLERANCE: u8 = 24;
+// Fraction of pixels that may differ, for noise and driver differences
+const MAX_DIFFERING: f64 = 0.01;
+
+fn path(relative: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+/**
+ * The demo scene with one of the models from resources/
+ */
+pub fn scene(model: &str) -> Scene {
+    let path = path("resources").join(model);
+    Scene {
+        object: object::load_object(path.to_str().unwrap()),
+        materials: vec![Material {
+            color: [0.0, 0.0, 0.5, 1.0],
+            roughness: 0.3,
+            reflectivity: 0.2,
+            ..Default::default()
+        }],
+        lights: vec![
+            Light::point([0.0, 3.1, 3.0], [1.0, 1.0, 1.0], 12.0),
+            Light::spot(
+                [2.5, 2.5, -2.0],
+                [-2.5, -2.4, 2.0],
+                0.2,
+                0.35,
+                [1.0, 0.8, 0.5],
+                20.0,
+            ),
+            Light::area(
+                [-1.0, 2.95, -1.0],
+                [2.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0],
+                [0.6, 0.7, 1.0],
+                4.0,
+            ),
+        ],
+        environment: EnvironmentMap::constant([0.0, 0.0, 0.0]),
+        textures: texture::load_textures(&[]).unwrap(),
+    }
+}
+
+pub fn tone_mapping() -> ToneMapping {
+    ToneMapping {
+        operator: Operator::Aces,
+        exposure: 0.0,
+        gamma: 2.2,
+    }
+}
+
+/**
+ * The camera at the start of the animation, looking at the room
+ */
+pub fn room_camera() -> Camera {
+    Camera::animated(0.0)
+}
+
+/**
+ * Close enough to the origin to fill the image with the bunny
+ */
+pub fn bunny_camera() -> Camera {
+    Camera {
+        position: [0.0, 0.08, -0.25],
+        direction: [0.0, 0.0, 1.0],
+    }
+}
+
+/**
+ * The golden image tests/golden/<name>.png
+ */
+pub fn golden(name: &str) -> RgbImage {
+    let golden_path = path("tests/golden").join(format!("{}.png", name));
+    if !golden_path.exists() {
+        panic!("no golden image for {}, run with UPDATE_GOLDEN=1", name);
+    }
+    image::open(&golden_path)
+        .expect("Failed to read golden image")
+        .to_rgb()
+}
+
+/**
+ * Compares an image with tests/golden/<name>.png, which is written instead
+ * when UPDATE_GOLDEN is set. On failure the image and the difference are
+ * written to target/golden.
+ */
+pub fn compare(name: &str, actual: &RgbImage) {
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        let golden_path = path("tests/golden").join(format!("{}.png", name));
+        actual.save(&golden_path).expect("Failed to write golden image");
+        println!("Wrote {}", golden_path.display());
+        return;
+    }
+    compare_images(name, &golden(name), actual);
+}
+
+/**
+ * Compares two images with the tolerances of the golden images, `name` is
+ * used for the files written on failure
+ */
+pub fn compare_images(name: &str, golden: &RgbImage, actual: &RgbImage) {
+    assert_eq!(
+        golden.dimensions(),
+        actual.dimensions(),
+        "{} has a different size than its golden image",
+        name
+    );
+
+    let mut diff = RgbImage::new(actual.width(), actual.height());
+    let mut squared_error = 0.0;
+    let mut differing = 0;
+    for (x, y, pixel) in actual.enumerate_pixels() {
+        let expected = golden.get_pixel(x, y);
+        let mut worst = 0;
+        let mut error = [0; 3];
+        for c in 0..3 {
+            let d = (i32::from(pixel[c]) - i32::from(expected[c])).abs();
+            squared_error += f64::from(d * d);
+            worst = worst.max(d);
+            // Amplified, so small differences are visible
+            error[c] = (d * 4).min(255) as u8;
+        }
+        if worst > i32::from(PIXEL_TOLERANCE) {
+            differing += 1;
+        }
+        diff.put_pixel(x, y, Rgb(error));
+    }
+
+    let channels = f64::from(actual.width() * actual.height() * 3);
+    let rmse = (squared_error / channels).sqrt();
+    let differing = f64::from(differing) / f64::from(actual.width() * actual.height());
+
+    if rmse > MAX_RMSE || differing > MAX_DIFFERING {
+        let out = path("target/golden");
+        fs::create_dir_all(&out).unwrap();
+        actual.save(out.join(format!("{}-actual.png", name))).unwrap();
+        diff.save(out.join(format!("{}-diff.png", name))).unwrap();
+        panic!(
+            "{} differs from its golden image: RMSE {:.2} (max {}), {:.2}% of pixels differ (max {}%), see {}",
+            name,
+            rmse,
+            MAX_RMSE,
+            differing * 100.0,
+            MAX_DIFFERING * 100.0,
+            out.display()
+        );
+    }
+}
@@ -0,0 +1,163 @@
+// Golden images of the Vulkan renderer, rendered without a window. Runs on
+// any device, like a software driver such as lavapipe or SwiftShader, and is
+// skipped when there is none.
+
+extern crate coding_assignment;
+extern crate image;
+extern crate vulkano;
+
+mod common;
+
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use coding_assignment::camera::Camera;
+use coding_assignment::renderer::{WorkgroupSize, DEFAULT_MAX_DEPTH};
+use coding_assignment::{Renderer, Scene, Settings};
+use image::{DynamicImage, RgbImage};
+use vulkano::device::{Device, DeviceExtensions, Queue};
+use vulkano::format::Format;
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract};
+use vulkano::image::{AttachmentImage, ImageUsage};
+use vulkano::instance::{Instance, InstanceExtensions, PhysicalDevice};
+use vulkano::sync::GpuFuture;
+
+const FORMAT: Format = Format::R8G8B8A8Unorm;
+
+// Written to stderr directly, which the test harness does not capture, so
+// skipped tests show up in the output of cargo test
+fn skip(reason: &str) {
+    let _ = writeln!(io::stderr(), "skipped: no Vulkan device ({})", reason);
+}
+
+fn device() -> Option<(Arc<Device>, Arc<Queue>)> {
+    let instance = match Instance::new(None, &InstanceExtensions::none(), None) {
+        Ok(instance) => instance,
+        Err(e) => {
+            skip(&e.to_string());
+            return None;
+        }
+    };
+    let physical = match PhysicalDevice::enumerate(&instance).next() {
+        Some(physical) => physical,
+        None => {
+            skip("no physical device");
+            return None;
+        }
+    };
+    let queue_family = match physical.queue_families().find(|q| q.supports_graphics()) {
+        Some(queue_family) => queue_family,
+        None => {
+            skip("no graphics queue");
+            return None;
+        }
+    };
+
+    let (device, mut queues) = Device::new(
+        physical,
+        physical.supported_features(),
+        &DeviceExtensions::none(),
+        [(queue_family, 0.5)].iter().cloned(),
+    ).expect("Failed to create device");
+    Some((device, queues.next().unwrap()))
+}
+
+/**
+ * Traces a single frame without post-processing
+ */
+fn render(scene: Scene, camera: Camera, time: f32, compute: bool) -> Option<RgbImage> {
+    let (device, queue) = device()?;
+
+    let (mut renderer, post_future) = Renderer::new(
+        device.clone(),
+        queue.clone(),
+        FORMAT,
+        common::SIZE,
+        Settings {
+            post: vec![],
+            compute,
+            workgroup_size: WorkgroupSize {
+                width: 8,
+                height: 8,
+            },
+            tile_size: 0,
+            tiles_per_submit: 1,
+            tone_mapping: common::tone_mapping(),
+            environment_rotation: 0.0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        },
+    );
+    let scene_future = renderer.load_scene(scene);
+
+    let target = AttachmentImage::with_usage(
+        device.clone(),
+        common::SIZE,
+        FORMAT,
+        ImageUsage {
+            color_attachment: true,
+            ..ImageUsage::none()
+        },
+    ).unwrap();
+    let framebuffer = Arc::new(
+        Framebuffer::start(renderer.output_pass())
+            .add(target)
+            .unwrap()
+            .build()
+            .unwrap(),
+    ) as Arc<FramebufferAbstract + Send + Sync>;
+
+    renderer.start_frame(camera, time);
+    let command_buffer = renderer.render_frame(framebuffer);
+    let future = post_future
+        .join(scene_future)
+        .then_execute(queue, command_buffer)
+        .unwrap();
+
+    Some(DynamicImage::ImageRgba8(renderer.screenshot(future)).to_rgb())
+}
+
+#[test]
+fn gpu_cube() {
+    let scene = common::scene("test-cube.ply");
+    if let Some(image) = render(scene, common::room_camera(), 0.0, true) {
+        common::compare("gpu_cube", &image);
+    }
+}
+
+// The fragment shader has to give the same image as the compute shader
+#[test]
+fn gpu_cube_fragment() {
+    let camera = common::room_camera();
+    let compute = render(common::scene("test-cube.ply"), camera, 0.0, true);
+    let fragment = render(common::scene("test-cube.ply"), camera, 0.0, false);
+    if let (Some(compute), Some(fragment)) = (compute, fragment) {
+        common::compare_images("gpu_cube_fragment", &compute, &fragment);
+    }
+}
+
+// Both renderers trace the same scene, so the shaders have to match the CPU
+#[test]
+fn gpu_cube_matches_reference() {
+    let scene = common::scene("test-cube.ply");
+    if let Some(image) = render(scene, common::room_camera(), 0.0, true) {
+        let reference = common::golden("reference_cube");
+        common::compare_images("gpu_cube_reference", &reference, &image);
+    }
+}
+
+#[test]
+fn gpu_bunny() {
+    let scene = common::scene("bunny_low_res.ply");
+    if let Some(image) = render(scene, common::bunny_camera(), 0.0, true) {
+        common::compare("gpu_bunny", &image);
+    }
+}
+
+// The distance field objects are animated, the bunny is too small to matter
+#[test]
+fn gpu_sdf_room() {
+    let scene = common::scene("bunny_low_res.ply");
+    if let Some(image) = render(scene, Camera::animated(1.0), 1.0, true) {
+        common::compare("gpu_sdf_room", &image);
+    }
+}
@@ -0,0 +1,41 @@
+// Golden images of the CPU reference renderer. They run everywhere, without
+// a Vulkan driver.
+
+extern crate coding_assignment;
+extern crate image;
+
+mod common;
+
+use std::sync::Arc;
+
+use coding_assignment::camera::Camera;
+use coding_assignment::reference::{self, Settings};
+use coding_assignment::renderer::DEFAULT_MAX_DEPTH;
+use coding_assignment::Scene;
+use image::RgbImage;
+
+fn render(scene: Scene, camera: Camera) -> RgbImage {
+    reference::render(
+        Arc::new(scene),
+        Settings {
+            dimensions: common::SIZE,
+            camera,
+            max_depth: DEFAULT_MAX_DEPTH,
+            environment_rotation: 0.0,
+            tone_mapping: common::tone_mapping(),
+            threads: 4,
+        },
+    )
+}
+
+#[test]
+fn reference_cube() {
+    let image = render(common::scene("test-cube.ply"), common::room_camera());
+    common::compare("reference_cube", &image);
+}
+
+#[test]
+fn reference_bunny() {
+    let image = render(common::scene("bunny_low_res.ply"), common::bunny_camera());
+    common::compare("reference_bunny", &image);
+}